@@ -0,0 +1,48 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use red_cod::{Codebox, Pos};
+
+fn bench_wide_line(c: &mut Criterion) {
+    let code = "1".repeat(10_000);
+    c.bench_function("parse 10000-wide line", |b| b.iter(|| Codebox::new(&code)));
+}
+
+// one long line among many short ones: every row is padded out to the width of
+// the long line, so this costs as much as the full 10000x1001 rectangle
+fn bench_wide_line_among_short(c: &mut Criterion) {
+    let mut code = "1".repeat(10_000);
+    for _ in 0..1_000 {
+        code.push_str("\n1 2");
+    }
+    c.bench_function("parse 10000-wide line among 1000 short", |b| {
+        b.iter(|| Codebox::new(&code))
+    });
+}
+
+fn bench_square(c: &mut Criterion) {
+    let code = vec!["1".repeat(100); 100].join("\n");
+    c.bench_function("parse 100x100 square", |b| b.iter(|| Codebox::new(&code)));
+}
+
+// the interpreter looks up the instruction under the pointer on every step,
+// so this is the hot path of any long-running program
+fn bench_lookup(c: &mut Criterion) {
+    let codebox = Codebox::new(&vec!["1 ".repeat(50); 100].join("\n"));
+    c.bench_function("look up every cell of 100x100 square", |b| {
+        b.iter(|| {
+            for y in 0..100 {
+                for x in 0..100 {
+                    black_box(codebox.get_instruction(&Pos { x, y }));
+                }
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_wide_line,
+    bench_wide_line_among_short,
+    bench_square,
+    bench_lookup
+);
+criterion_main!(benches);
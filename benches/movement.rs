@@ -0,0 +1,21 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use red_cod::Interpreter;
+use std::iter::empty;
+
+// counts to 100, crossing a wide run of noops on every iteration
+fn wide_sparse_program() -> String {
+    format!("1+:aa*=?;{}", " ".repeat(10_000))
+}
+
+fn bench_wide_sparse(c: &mut Criterion) {
+    let code = wide_sparse_program();
+    c.bench_function("wide sparse loop", |b| {
+        b.iter(|| {
+            let mut interpreter = Interpreter::new(&code, empty()).with_initial_stack(vec![0f64]);
+            interpreter.run_to_end().unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, bench_wide_sparse);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+use red_cod::{Interpreter, Pos, State};
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::read_to_string;
+use std::io::{self, BufRead};
+use std::iter::empty;
+
+const USAGE: &str = "usage: debug <program>";
+const HELP: &str =
+    "commands: s (step), c (continue), b <x>,<y> (set breakpoint), p (print stack), q (quit)";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let file = std::env::args().nth(1).ok_or(USAGE)?;
+    let data = read_to_string(file)?;
+
+    // stdin is taken up by commands, so the program gets no input
+    let mut interpreter = Interpreter::new(&data, empty());
+    let mut breakpoints = HashSet::new();
+
+    println!("{}", interpreter.render_with_pointer());
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("s"), None) if *interpreter.state() == State::Done => {
+                println!("program has already finished")
+            }
+            (Some("s"), None) => {
+                if let Err(err) = interpreter.step() {
+                    println!("{}", interpreter.diagnose(&err));
+                }
+            }
+            (Some("c"), None) => match interpreter.run_to_breakpoint(&breakpoints) {
+                Ok(true) => println!("stopped at breakpoint"),
+                Ok(false) => (),
+                Err(err) => println!("{}", interpreter.diagnose(&err)),
+            },
+            (Some("b"), Some(pos)) => match parse_pos(pos) {
+                Some(pos) => {
+                    breakpoints.insert(pos);
+                    println!("breakpoint set at ({}, {})", pos.x, pos.y);
+                }
+                None => println!("{}", HELP),
+            },
+            (Some("p"), None) => println!("{}", interpreter.render_stack()),
+            (Some("q"), None) => break,
+            _ => println!("{}", HELP),
+        }
+
+        println!();
+        println!("{}", interpreter.render_with_pointer());
+        if *interpreter.state() == State::Done {
+            println!("program finished");
+        }
+    }
+    Ok(())
+}
+
+/// Parses a position written as `x,y`.
+fn parse_pos(s: &str) -> Option<Pos> {
+    let mut coords = s.split(',').map(|coord| coord.trim().parse().ok());
+    match (coords.next(), coords.next(), coords.next()) {
+        (Some(Some(x)), Some(Some(y)), None) => Some(Pos { x, y }),
+        _ => None,
+    }
+}
@@ -1,44 +1,242 @@
 #![feature(backtrace)]
 
-use red_cod::Interpreter;
+use red_cod::{parse_stack_value, Interpreter, OpCategory};
 
 use std::error::Error;
-use std::fs::read_to_string;
-use std::io::{self, Read, Stdin};
+use std::fs::{read_to_string, write};
+use std::io::{self, Read};
 use std::os::unix::io::AsRawFd;
+use std::process;
 use termios::{tcsetattr, Termios, ECHO, ICANON, TCSANOW};
 
+const USAGE: &str = "usage: fish [--input <text> | --input-file <file>] [--input-eof <value>] \
+                     [--stack <v1,v2,...>] [--load-stack <file>] [--dump-stack <file>] [--stats] \
+                     [--step-limit <n>] [--print-stack] <program>";
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<_> = std::env::args().collect();
-    let file = args.get(1).unwrap();
-    let data = read_to_string(file)?;
+    let mut args = std::env::args().skip(1);
+    let mut file = None;
+    let mut eof_value = None;
+    let mut load_stack = None;
+    let mut stack_values = Vec::new();
+    let mut dump_stack = None;
+    let mut show_stats = false;
+    let mut step_limit = None;
+    let mut print_stack = false;
+    let mut input = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input = Some(args.next().ok_or(USAGE)?),
+            "--input-file" => input = Some(read_to_string(args.next().ok_or(USAGE)?)?),
+            "--input-eof" => {
+                let value = args.next().ok_or(USAGE)?;
+                eof_value = Some(value.parse::<f64>()?);
+            }
+            "--stack" => {
+                let values = args.next().ok_or(USAGE)?;
+                for value in values.split(',').filter(|value| !value.trim().is_empty()) {
+                    stack_values.push(parse_stack_value(value)?);
+                }
+            }
+            "--load-stack" => load_stack = Some(args.next().ok_or(USAGE)?),
+            "--dump-stack" => dump_stack = Some(args.next().ok_or(USAGE)?),
+            "--stats" => show_stats = true,
+            "--step-limit" => step_limit = Some(args.next().ok_or(USAGE)?.parse::<u64>()?),
+            "--print-stack" => print_stack = true,
+            _ if arg.starts_with("--") || file.is_some() => Err(USAGE)?,
+            _ => file = Some(arg),
+        }
+    }
+    let data = read_to_string(file.ok_or(USAGE)?)?;
+    // values given with --stack go on top of any loaded stack
+    let mut initial_stack = match load_stack {
+        Some(path) => read_stack(&path)?,
+        None => Vec::new(),
+    };
+    initial_stack.extend(stack_values);
 
     // termios code based on https://stackoverflow.com/a/37416107
+    // stdin may not be a terminal (e.g. when piped), in which case there's no
+    // mode to change, and it's left alone entirely when input is given by flag
     let stdin_fd = io::stdin().as_raw_fd();
-    let termios = Termios::from_fd(stdin_fd).expect("failed to open stdin from fd");
-    let mut new_termios = termios.clone(); // make a mutable copy of termios
-                                           // that we will modify
-    new_termios.c_lflag &= !(ICANON | ECHO); // no echo and canonical mode
-    tcsetattr(stdin_fd, TCSANOW, &mut new_termios).expect("failed to enter raw mode");
-
-    let stdin_iter = StdinIter(io::stdin());
-    let mut interpreter = Interpreter::new(&data, stdin_iter);
+    let termios = match input {
+        Some(_) => None,
+        None => Termios::from_fd(stdin_fd).ok(),
+    };
+    if let Some(termios) = &termios {
+        let mut new_termios = termios.clone(); // make a mutable copy of termios
+                                               // that we will modify
+        new_termios.c_lflag &= !(ICANON | ECHO); // no echo and canonical mode
+        tcsetattr(stdin_fd, TCSANOW, &new_termios).expect("failed to enter raw mode");
+    }
+
+    let input_stream: Box<dyn Iterator<Item = char>> = match input {
+        Some(input) => Box::new(input.chars().collect::<Vec<_>>().into_iter()),
+        None => Box::new(StdinIter::new(io::stdin())),
+    };
+    let mut interpreter =
+        Interpreter::new(&data, input_stream).try_with_initial_stack(initial_stack)?;
+    if let Some(eof_value) = eof_value {
+        interpreter = interpreter.with_eof_value(eof_value);
+    }
+    interpreter.set_step_limit(step_limit);
     let res = interpreter.run_to_end();
 
-    tcsetattr(stdin_fd, TCSANOW, &termios).expect("failed to restore tty state");
+    if let Some(termios) = &termios {
+        tcsetattr(stdin_fd, TCSANOW, termios).expect("failed to restore tty state");
+    }
+
+    // with --print-stack, a program that output nothing gives back its stack
+    // instead, so it can be used like a function
+    if print_stack && interpreter.output_len() == 0 {
+        let values: Vec<_> = interpreter
+            .stack_snapshot()
+            .iter()
+            .map(|val| val.to_string())
+            .collect();
+        println!("{}", values.join(" "));
+    } else {
+        println!();
+    }
+    if let Some(path) = dump_stack {
+        let lines: Vec<_> = interpreter
+            .stack()
+            .flatten()
+            .iter()
+            .map(|val| format!("{}\n", val))
+            .collect();
+        write(path, lines.concat())?;
+    }
+    if show_stats {
+        let stats = interpreter.stats();
+        eprintln!("steps: {}", stats.steps);
+        for &category in OpCategory::ALL.iter() {
+            eprintln!("  {}: {}", category.name(), stats.count(category));
+        }
+        eprintln!("peak stack depth: {}", stats.peak_stack_depth);
+        eprintln!(
+            "self-modified: {}",
+            if stats.self_modified { "yes" } else { "no" }
+        );
+    }
+    if let Err(err) = res {
+        eprintln!("{}", interpreter.diagnose(&err));
+        process::exit(err.exit_code());
+    }
+    Ok(())
+}
+
+/// Reads a stack saved with `--dump-stack`: one value per line, bottom first.
+fn read_stack(path: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_stack_value(line).map_err(|err| format!("{} in {}", err, path).into()))
+        .collect()
+}
 
-    println!();
-    Ok(res?)
+/// Decodes UTF-8 from a reader, one character at a time. Malformed sequences
+/// come out as U+FFFD.
+struct StdinIter<R> {
+    input: R,
+    // a byte that cut a character short, to start the next one
+    pending: Option<u8>,
 }
 
-struct StdinIter(Stdin);
+impl<R: Read> StdinIter<R> {
+    fn new(input: R) -> Self {
+        Self {
+            input,
+            pending: None,
+        }
+    }
 
-impl Iterator for StdinIter {
+    fn next_byte(&mut self) -> Option<u8> {
+        if let Some(byte) = self.pending.take() {
+            return Some(byte);
+        }
+        let mut byte = [0];
+        self.input.read_exact(&mut byte).ok()?;
+        Some(byte[0])
+    }
+}
+
+impl<R: Read> Iterator for StdinIter<R> {
     type Item = char;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut buf = [0; 1];
-        self.0.read_exact(&mut buf).ok()?;
-        Some(buf[0] as char)
+        let mut buf = [0; 4];
+        buf[0] = self.next_byte()?;
+        let len = match buf[0] {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Some(char::REPLACEMENT_CHARACTER),
+        };
+        for slot in buf[1..len].iter_mut() {
+            match self.next_byte() {
+                Some(byte) if byte & 0xc0 == 0x80 => *slot = byte,
+                // only the bytes read so far are replaced; this one may
+                // start a character of its own
+                Some(byte) => {
+                    self.pending = Some(byte);
+                    return Some(char::REPLACEMENT_CHARACTER);
+                }
+                None => return Some(char::REPLACEMENT_CHARACTER),
+            }
+        }
+        let decoded = std::str::from_utf8(&buf[..len]).ok();
+        Some(
+            decoded
+                .and_then(|s| s.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StdinIter;
+
+    #[test]
+    fn test_stdin_iter_decodes_utf8() {
+        let input = "aé€🐟".as_bytes();
+        assert_eq!(
+            StdinIter::new(input).collect::<Vec<_>>(),
+            vec!['a', 'é', '€', '🐟']
+        );
+
+        let input: &[u8] = &[b'a', 0xff, 0xe2, 0x82];
+        assert_eq!(
+            StdinIter::new(input).collect::<Vec<_>>(),
+            vec![
+                'a',
+                char::REPLACEMENT_CHARACTER,
+                char::REPLACEMENT_CHARACTER
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stdin_iter_resyncs_after_invalid_bytes() {
+        let input: &[u8] = &[0xff, b'a'];
+        assert_eq!(
+            StdinIter::new(input).collect::<Vec<_>>(),
+            vec![char::REPLACEMENT_CHARACTER, 'a']
+        );
+
+        // a lead byte followed by something other than a continuation byte
+        let input: &[u8] = &[0xe2, b'a', b'b', 0xc3, 0xe2, 0x82, 0xac];
+        assert_eq!(
+            StdinIter::new(input).collect::<Vec<_>>(),
+            vec![
+                char::REPLACEMENT_CHARACTER,
+                'a',
+                'b',
+                char::REPLACEMENT_CHARACTER,
+                '€'
+            ]
+        );
     }
 }
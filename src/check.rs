@@ -0,0 +1,105 @@
+use crate::interpreter::{Interpreter, RuntimeError};
+
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// How a program's output differed from what was expected, as found by
+/// `check_output`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputMismatch {
+    /// The index of the first character that differs. If one output is a
+    /// prefix of the other, this is the length of the shorter one.
+    pub position: usize,
+    pub expected: String,
+    pub actual: String,
+    /// The error the program stopped with, if it didn't finish normally.
+    /// Its output up to that point is still compared.
+    pub error: Option<RuntimeError>,
+}
+
+impl Display for OutputMismatch {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        if let Some(err) = &self.error {
+            write!(f, "program failed with {}; ", err)?;
+        }
+        write!(
+            f,
+            "output differs at character {}: expected {:?}, got {:?}",
+            self.position, self.expected, self.actual
+        )
+    }
+}
+
+/// Runs `code` with `input` as its input stream and compares everything it
+/// outputs against `expected`. The program must also finish without error.
+pub fn check_output(code: &str, input: &str, expected: &str) -> Result<(), OutputMismatch> {
+    let output = Rc::new(RefCell::new(vec![]));
+    let mut interpreter =
+        Interpreter::new(code, input.chars()).with_output(Captured(Rc::clone(&output)));
+    let error = interpreter.run_to_end().err();
+    let actual = String::from_utf8_lossy(&output.borrow()).into_owned();
+
+    if error.is_none() && actual == expected {
+        return Ok(());
+    }
+    let position = expected
+        .chars()
+        .zip(actual.chars())
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or_else(|| expected.chars().count().min(actual.chars().count()));
+    Err(OutputMismatch {
+        position,
+        expected: expected.to_string(),
+        actual,
+        error,
+    })
+}
+
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codebox::Pos;
+    use crate::stack::StackError;
+
+    #[test]
+    fn test_check_output() {
+        assert_eq!(check_output("i:o1+o;", "a", "ab"), Ok(()));
+
+        assert_eq!(
+            check_output("i:o1+o;", "a", "ac"),
+            Err(OutputMismatch {
+                position: 1,
+                expected: "ac".to_string(),
+                actual: "ab".to_string(),
+                error: None,
+            })
+        );
+
+        assert_eq!(
+            check_output("'a'o+;", "", "a"),
+            Err(OutputMismatch {
+                position: 1,
+                expected: "a".to_string(),
+                actual: "a".to_string(),
+                error: Some(RuntimeError::StackError(
+                    StackError::Underflow,
+                    Pos { x: 4, y: 0 }
+                )),
+            })
+        );
+    }
+}
@@ -1,4 +1,35 @@
-use std::collections::HashMap;
+use crate::interpreter::OpCategory;
+
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+/// Every character that ><> defines as an instruction, grouped by category.
+/// This is the one list of valid ops; `OpCategory::of` and `is_valid_op` both
+/// look characters up here.
+pub const OPS: [(OpCategory, &str); 9] = [
+    (OpCategory::Literal, "0123456789abcdef"),
+    (OpCategory::Arithmetic, "+-*,%"),
+    (OpCategory::Comparison, "=)("),
+    (OpCategory::StackManipulation, ":~$@}{[]lr&"),
+    (OpCategory::Trampoline, "!?"),
+    (OpCategory::Movement, "^>v</\\|_#x."),
+    (OpCategory::InputOutput, "\"'noi"),
+    (OpCategory::CodeboxManipulation, "gp"),
+    (OpCategory::Other, ";"),
+];
+
+// the most cells the dense grid may grow to; cells written further out are
+// kept in a map instead, so that a `p` far from the program can't exhaust
+// memory
+const MAX_GRID_CELLS: usize = 1 << 24;
+
+/// Whether `chr` is one of the instructions in `OPS`.
+pub fn is_valid_op(chr: char) -> bool {
+    OPS.iter().any(|(_, ops)| ops.contains(chr))
+}
 
 #[derive(Debug, Hash, PartialEq, Eq, Copy, Clone)]
 pub struct Pos {
@@ -6,58 +37,261 @@ pub struct Pos {
     pub y: usize,
 }
 
+impl Pos {
+    /// The position `dx` across and `dy` down from this one, or `None` if
+    /// that would be above or left of the origin.
+    pub fn offset(&self, dx: isize, dy: isize) -> Option<Pos> {
+        Some(Pos {
+            x: offset_coord(self.x, dx)?,
+            y: offset_coord(self.y, dy)?,
+        })
+    }
+}
+
+fn offset_coord(coord: usize, delta: isize) -> Option<usize> {
+    if delta < 0 {
+        coord.checked_sub(delta.unsigned_abs())
+    } else {
+        coord.checked_add(delta as usize)
+    }
+}
+
+// positions are ordered row by row, as they'd be read
+impl Ord for Pos {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl PartialOrd for Pos {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Instruction {
     Noop,
     Op(char),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Codebox {
-    code: HashMap<Pos, Instruction>,
+    // every cell of the dense grid, row by row, with each row padded to the
+    // full width
+    code: Vec<Instruction>,
     width: usize,
     height: usize,
+    // ops outside the grid, where growing it to cover them would be too
+    // costly, and the size of the area holding them as well as the grid
+    far: HashMap<Pos, char>,
+    extent: (usize, usize),
+
+    // positions of every op, indexed by row and by column, so that runs of
+    // noops can be skipped without visiting each cell
+    rows: BTreeMap<usize, BTreeSet<usize>>,
+    cols: BTreeMap<usize, BTreeSet<usize>>,
+    // the hashes of every op xored together, kept up to date as ops are
+    // written so that hashing the codebox doesn't have to visit them all
+    ops_hash: u64,
 }
 
 impl Codebox {
     pub fn new(code: &str) -> Self {
-        let lines: Vec<_> = code.lines().map(String::from).collect();
-        let width = lines
-            .iter()
-            .max_by_key(|l| l.len())
-            .unwrap_or(&String::new())
-            .len();
-        let height = lines.len();
-        let mut code = HashMap::new();
-
-        for (y, line) in lines.into_iter().enumerate() {
-            for (x, chr) in line.chars().enumerate() {
-                code.insert(
-                    Pos { x, y },
-                    if chr == ' ' {
-                        Instruction::Noop
+        Self::from_grid(code.lines().map(|line| line.chars().collect()).collect())
+    }
+
+    /// Like `new`, but expands each tab into noops up to the next multiple of
+    /// `tab_width` columns, so that cells line up as they would in an editor.
+    /// `new` treats a tab as a single cell, like a tab width of 1.
+    pub fn with_tab_width(code: &str, tab_width: usize) -> Self {
+        let tab_width = tab_width.max(1);
+        let grid = code
+            .lines()
+            .map(|line| {
+                let mut row = vec![];
+                for chr in line.chars() {
+                    if chr == '\t' {
+                        let stop = (row.len() / tab_width + 1) * tab_width;
+                        row.resize(stop, ' ');
                     } else {
-                        // technically, some of these ops might be invalid
-                        // we'll handle that during interpretation
-                        Instruction::Op(chr)
-                    },
-                );
-            }
-        }
+                        row.push(chr);
+                    }
+                }
+                row
+            })
+            .collect();
+        Self::from_grid(grid)
+    }
 
-        Self {
-            code,
+    /// Builds a codebox from rows of cells. Rows may be ragged; spaces become
+    /// noops.
+    ///
+    /// Cells are stored in a flat grid so that lookups are cheap, which means
+    /// short rows are padded out to the width of the widest one, and a single
+    /// long line among many short ones costs as much as the full rectangle
+    /// (see `benches/codebox.rs`). The grid is capped in size, though; cells
+    /// past the cap are kept sparsely, outside `width` and `height`.
+    pub fn from_grid(grid: Vec<Vec<char>>) -> Self {
+        let full_width = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+        let full_height = grid.len();
+        let width = full_width.min(MAX_GRID_CELLS / full_height.max(1));
+        let height = full_height.min(MAX_GRID_CELLS / width.max(1));
+        let mut codebox = Self {
+            code: vec![Instruction::Noop; width * height],
             width,
             height,
+            far: HashMap::new(),
+            extent: (full_width, full_height),
+            rows: BTreeMap::new(),
+            cols: BTreeMap::new(),
+            ops_hash: 0,
+        };
+
+        for (y, row) in grid.into_iter().enumerate() {
+            for (x, chr) in row.into_iter().enumerate() {
+                if chr != ' ' {
+                    // technically, some of these ops might be invalid
+                    // we'll handle that during interpretation
+                    codebox.set_instruction(Pos { x, y }, chr);
+                }
+            }
         }
+
+        codebox
     }
 
     pub fn get_instruction(&self, pos: &Pos) -> Instruction {
-        *self.code.get(pos).unwrap_or(&Instruction::Noop)
+        if pos.x < self.width && pos.y < self.height {
+            self.code[pos.y * self.width + pos.x]
+        } else {
+            self.far
+                .get(pos)
+                .map_or(Instruction::Noop, |&chr| Instruction::Op(chr))
+        }
     }
 
+    /// Places `instr` at `pos`, growing the codebox to fit if `pos` lies
+    /// outside it. Cells far enough out that padding the grid to reach them
+    /// would take too much memory are stored sparsely instead, and don't
+    /// count towards `width` and `height`; see `extent`.
     pub fn set_instruction(&mut self, pos: Pos, instr: char) {
-        self.code.insert(pos, Instruction::Op(instr));
+        if let Instruction::Op(old) = self.get_instruction(&pos) {
+            self.ops_hash ^= op_hash(pos, old);
+        }
+        self.ops_hash ^= op_hash(pos, instr);
+        // a cell on the very last coordinate can't be counted in the size,
+        // which stops one short of it
+        self.extent = (
+            self.extent.0.max(pos.x.saturating_add(1)),
+            self.extent.1.max(pos.y.saturating_add(1)),
+        );
+        let grid_size = pos
+            .x
+            .checked_add(1)
+            .zip(pos.y.checked_add(1))
+            .and_then(|(x, y)| {
+                let (width, height) = (self.width.max(x), self.height.max(y));
+                width
+                    .checked_mul(height)
+                    .filter(|&cells| cells <= MAX_GRID_CELLS)
+                    .map(|_| (width, height))
+            });
+        match grid_size {
+            Some((width, height)) => {
+                self.grow(width, height);
+                self.code[pos.y * self.width + pos.x] = Instruction::Op(instr);
+            }
+            None => {
+                self.far.insert(pos, instr);
+            }
+        }
+        self.rows.entry(pos.y).or_default().insert(pos.x);
+        self.cols.entry(pos.x).or_default().insert(pos.y);
+    }
+
+    // pads the grid with noops until it is at least `width` by `height`. A far
+    // cell is only made when covering it would overflow the grid, and the grid
+    // never shrinks, so no far cell ever ends up inside it
+    fn grow(&mut self, width: usize, height: usize) {
+        if width > self.width {
+            let mut code = Vec::with_capacity(width * self.height.max(height));
+            for y in 0..self.height {
+                code.extend_from_slice(&self.code[y * self.width..(y + 1) * self.width]);
+                code.resize(code.len() + width - self.width, Instruction::Noop);
+            }
+            self.code = code;
+            self.width = width;
+        }
+        if height > self.height {
+            self.code.resize(self.width * height, Instruction::Noop);
+            self.height = height;
+        }
+    }
+
+    /// Checks every cell against `OPS`, returning all the invalid characters
+    /// found, in row-major order. Characters between a pair of matching quotes
+    /// in the same row, or after an unmatched quote up to the end of its row,
+    /// are taken to be string contents and are allowed, but strings read
+    /// vertically or right to left aren't recognised.
+    pub fn validate(&self) -> Result<(), Vec<(Pos, char)>> {
+        let mut invalid = vec![];
+        let mut quote = None;
+        let mut row = 0;
+        for (pos, chr) in self.ops() {
+            if pos.y != row {
+                quote = None;
+                row = pos.y;
+            }
+            match quote {
+                Some(open) if chr == open => quote = None,
+                Some(_) => {}
+                None if chr == '"' || chr == '\'' => quote = Some(chr),
+                None if !is_valid_op(chr) => invalid.push((pos, chr)),
+                None => {}
+            }
+        }
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid)
+        }
+    }
+
+    /// Every op in the codebox, in row-major order.
+    pub fn ops(&self) -> impl Iterator<Item = (Pos, char)> + '_ {
+        self.rows.iter().flat_map(move |(&y, xs)| {
+            xs.iter().map(move |&x| {
+                let pos = Pos { x, y };
+                match self.get_instruction(&pos) {
+                    Instruction::Op(chr) => (pos, chr),
+                    Instruction::Noop => unreachable!("indexed cell {:?} is a noop", pos),
+                }
+            })
+        })
+    }
+
+    /// The distinct characters in the codebox, sorted. Characters inside
+    /// strings are included, since they can't be told apart from ops without
+    /// running the program.
+    pub fn distinct_ops(&self) -> BTreeSet<char> {
+        self.ops().map(|(_, chr)| chr).collect()
+    }
+
+    /// Finds the nearest op in `pos`'s row, starting from `pos` itself and
+    /// moving right (or left, if `forward` is false), wrapping around within
+    /// the columns in `bounds`. `pos` must lie within `bounds`.
+    pub fn find_op_in_row(&self, pos: &Pos, bounds: Range<usize>, forward: bool) -> Option<Pos> {
+        let x = find_wrapped(self.rows.get(&pos.y)?, pos.x, bounds, forward)?;
+        Some(Pos { x, y: pos.y })
+    }
+
+    /// Finds the nearest op in `pos`'s column, starting from `pos` itself and
+    /// moving down (or up, if `forward` is false), wrapping around within the
+    /// rows in `bounds`. `pos` must lie within `bounds`.
+    pub fn find_op_in_col(&self, pos: &Pos, bounds: Range<usize>, forward: bool) -> Option<Pos> {
+        let y = find_wrapped(self.cols.get(&pos.x)?, pos.y, bounds, forward)?;
+        Some(Pos { x: pos.x, y })
     }
 
     pub fn width(&self) -> usize {
@@ -67,4 +301,414 @@ impl Codebox {
     pub fn height(&self) -> usize {
         self.height
     }
+
+    /// The width and height of the area holding every op, including any kept
+    /// outside the grid. This is what the instruction pointer wraps around.
+    pub fn extent(&self) -> (usize, usize) {
+        self.extent
+    }
+
+    /// Roughly how many bytes the cells and the op indexes take up.
+    pub fn approx_memory_bytes(&self) -> usize {
+        let indexed: usize = self.rows.values().map(BTreeSet::len).sum();
+        self.code.capacity() * std::mem::size_of::<Instruction>()
+            + self.far.capacity() * std::mem::size_of::<(Pos, char)>()
+            + 2 * indexed * std::mem::size_of::<usize>()
+    }
+
+    /// Which cells of the grid hold ops, row by row, with every row the full
+    /// width. Ops kept outside the grid are left out.
+    pub fn occupancy(&self) -> Vec<Vec<bool>> {
+        let mut grid = vec![vec![false; self.width]; self.height];
+        for (pos, _) in self.ops() {
+            if pos.x < self.width && pos.y < self.height {
+                grid[pos.y][pos.x] = true;
+            }
+        }
+        grid
+    }
+
+    /// Reconstructs the grid as text, one line per row, with noops as spaces
+    /// and every row padded to the full width. Ops kept outside the grid are
+    /// left out.
+    pub fn render(&self) -> String {
+        self.render_rows(|_, chr| chr.to_string())
+    }
+
+    /// Like `render`, but with the cell at `ptr` shown in inverse video.
+    pub fn render_with_pointer(&self, ptr: &Pos) -> String {
+        self.render_rows(|pos, chr| {
+            if pos == *ptr {
+                format!("\x1b[7m{}\x1b[0m", chr)
+            } else {
+                chr.to_string()
+            }
+        })
+    }
+
+    /// Reconstructs the grid as text like `render`, rendering
+    /// each cell with `render_cell`, which is given noops as spaces.
+    pub(crate) fn render_rows<F: Fn(Pos, char) -> String>(&self, render_cell: F) -> String {
+        let rows: Vec<String> = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        let pos = Pos { x, y };
+                        let chr = match self.get_instruction(&pos) {
+                            Instruction::Op(chr) => chr,
+                            Instruction::Noop => ' ',
+                        };
+                        render_cell(pos, chr)
+                    })
+                    .collect()
+            })
+            .collect();
+        rows.join("\n")
+    }
+}
+
+// codeboxes are compared by their ops alone, regardless of how blank cells
+// happen to be stored
+impl PartialEq for Codebox {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width && self.height == other.height && self.ops().eq(other.ops())
+    }
+}
+
+impl Eq for Codebox {}
+
+impl Hash for Codebox {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.height.hash(state);
+        self.ops_hash.hash(state);
+    }
+}
+
+fn op_hash(pos: Pos, instr: char) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (pos, instr).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn find_wrapped(
+    coords: &BTreeSet<usize>,
+    start: usize,
+    bounds: Range<usize>,
+    forward: bool,
+) -> Option<usize> {
+    if forward {
+        coords
+            .range(start..bounds.end)
+            .next()
+            .or_else(|| coords.range(bounds.start..start).next())
+    } else {
+        coords
+            .range(bounds.start..=start)
+            .next_back()
+            .or_else(|| coords.range(start..bounds.end).next_back())
+    }
+    .copied()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pos_offset() {
+        let pos = Pos { x: 1, y: 2 };
+        assert_eq!(pos.offset(2, -1), Some(Pos { x: 3, y: 1 }));
+        assert_eq!(pos.offset(-1, -2), Some(Pos { x: 0, y: 0 }));
+        assert_eq!(pos.offset(-2, 0), None);
+        assert_eq!(pos.offset(0, -3), None);
+    }
+
+    #[test]
+    fn test_pos_ord() {
+        let mut positions = vec![
+            Pos { x: 0, y: 1 },
+            Pos { x: 2, y: 0 },
+            Pos { x: 1, y: 1 },
+            Pos { x: 0, y: 0 },
+        ];
+        positions.sort();
+        assert_eq!(
+            positions,
+            vec![
+                Pos { x: 0, y: 0 },
+                Pos { x: 2, y: 0 },
+                Pos { x: 0, y: 1 },
+                Pos { x: 1, y: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_with_tab_width() {
+        let codebox = Codebox::with_tab_width("\t1\n23\tn\n\t\t;", 4);
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 4, y: 0 }),
+            Instruction::Op('1')
+        );
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 0, y: 0 }),
+            Instruction::Noop
+        );
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 4, y: 1 }),
+            Instruction::Op('n')
+        );
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 8, y: 2 }),
+            Instruction::Op(';')
+        );
+        assert_eq!(codebox.width(), 9);
+
+        let codebox = Codebox::new("\t1");
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 1, y: 0 }),
+            Instruction::Op('1')
+        );
+    }
+
+    #[test]
+    fn test_occupancy() {
+        let mut codebox = Codebox::new("1 n\n;");
+        assert_eq!(
+            codebox.occupancy(),
+            vec![vec![true, false, true], vec![true, false, false]]
+        );
+
+        codebox.set_instruction(Pos { x: 2, y: 1 }, 'o');
+        assert_eq!(
+            codebox.occupancy(),
+            vec![vec![true, false, true], vec![true, false, true]]
+        );
+    }
+
+    #[test]
+    fn test_set_instruction_grows() {
+        let mut codebox = Codebox::new("1n;");
+        codebox.set_instruction(Pos { x: 10, y: 10 }, 'o');
+        assert_eq!(codebox.width(), 11);
+        assert_eq!(codebox.height(), 11);
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 10, y: 10 }),
+            Instruction::Op('o')
+        );
+
+        codebox.set_instruction(Pos { x: 1, y: 1 }, 'o');
+        assert_eq!(codebox.width(), 11);
+        assert_eq!(codebox.height(), 11);
+        // existing cells keep their place when rows are widened
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 2, y: 0 }),
+            Instruction::Op(';')
+        );
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 0, y: 1 }),
+            Instruction::Noop
+        );
+
+        let mut codebox = Codebox::new("\n\n");
+        codebox.set_instruction(Pos { x: 1, y: 1 }, 'o');
+        assert_eq!(codebox.width(), 2);
+        assert_eq!(codebox.height(), 2);
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 1, y: 1 }),
+            Instruction::Op('o')
+        );
+    }
+
+    #[test]
+    fn test_set_instruction_far_away() {
+        let mut codebox = Codebox::new("1n;");
+        let far = Pos {
+            x: 1_000_000,
+            y: 1_000_000,
+        };
+        codebox.set_instruction(far, 'o');
+        assert_eq!(codebox.width(), 3);
+        assert_eq!(codebox.height(), 1);
+        assert_eq!(codebox.extent(), (1_000_001, 1_000_001));
+        assert_eq!(codebox.occupancy(), vec![vec![true, true, true]]);
+        assert_eq!(codebox.render_rows(|_, chr| chr.to_string()), "1n;");
+        assert_eq!(codebox.get_instruction(&far), Instruction::Op('o'));
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 2, y: 0 }),
+            Instruction::Op(';')
+        );
+        assert!(codebox.approx_memory_bytes() < 1 << 20);
+        assert_eq!(
+            codebox.find_op_in_row(&Pos { x: 0, y: far.y }, 0..codebox.extent().0, true),
+            Some(far)
+        );
+
+        // the grid keeps growing for nearby cells after a far one is written
+        codebox.set_instruction(Pos { x: 20, y: 3 }, 'n');
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 20, y: 3 }),
+            Instruction::Op('n')
+        );
+        assert_eq!(codebox.get_instruction(&far), Instruction::Op('o'));
+
+        let edge = Pos {
+            x: usize::MAX,
+            y: usize::MAX,
+        };
+        codebox.set_instruction(edge, 'o');
+        assert_eq!(codebox.width(), 21);
+        assert_eq!(codebox.extent(), (usize::MAX, usize::MAX));
+        assert_eq!(codebox.get_instruction(&edge), Instruction::Op('o'));
+    }
+
+    #[test]
+    fn test_from_grid() {
+        let codebox = Codebox::from_grid(vec![vec!['1', ' ', 'n'], vec![';']]);
+        assert_eq!(codebox.width(), 3);
+        assert_eq!(codebox.height(), 2);
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 0, y: 0 }),
+            Instruction::Op('1')
+        );
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 1, y: 0 }),
+            Instruction::Noop
+        );
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 0, y: 1 }),
+            Instruction::Op(';')
+        );
+        assert_eq!(
+            codebox.get_instruction(&Pos { x: 2, y: 1 }),
+            Instruction::Noop
+        );
+    }
+
+    #[test]
+    fn test_hash_follows_writes() {
+        fn hash_of(codebox: &Codebox) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            codebox.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let codebox = Codebox::new("1n;");
+        let mut written = Codebox::new("1 ;");
+        written.set_instruction(Pos { x: 1, y: 0 }, 'x');
+        assert_ne!(hash_of(&written), hash_of(&codebox));
+        written.set_instruction(Pos { x: 1, y: 0 }, 'n');
+        assert!(written == codebox);
+        assert_eq!(hash_of(&written), hash_of(&codebox));
+    }
+
+    #[test]
+    fn test_from_grid_over_cap() {
+        let mut long_row = vec![' '; MAX_GRID_CELLS / 2];
+        long_row.push(';');
+        let codebox = Codebox::from_grid(vec![vec!['1'], long_row]);
+        assert_eq!(codebox.width(), MAX_GRID_CELLS / 2);
+        assert_eq!(codebox.height(), 2);
+        assert_eq!(codebox.extent(), (MAX_GRID_CELLS / 2 + 1, 2));
+        assert!(codebox.approx_memory_bytes() <= MAX_GRID_CELLS * 8);
+        assert_eq!(
+            codebox.get_instruction(&Pos {
+                x: MAX_GRID_CELLS / 2,
+                y: 1
+            }),
+            Instruction::Op(';')
+        );
+        assert!(codebox.occupancy()[0][0]);
+    }
+
+    #[test]
+    fn test_width_with_tied_rows() {
+        // the widest rows are counted in chars, not bytes, and every order of
+        // them gives the same width
+        let rows = ["12", "é🐟n", "abc", ";"];
+        for shift in 0..rows.len() {
+            let mut rotated = rows.to_vec();
+            rotated.rotate_left(shift);
+            let codebox = Codebox::new(&rotated.join("\n"));
+            assert_eq!(codebox.width(), 3);
+            assert_eq!(codebox.height(), 4);
+
+            let grid = rotated.iter().map(|row| row.chars().collect()).collect();
+            assert_eq!(Codebox::from_grid(grid).width(), 3);
+        }
+    }
+
+    #[test]
+    fn test_find_op() {
+        let codebox = Codebox::new("a  b \n     \n  c  ");
+        let find_row = |x, y, forward| codebox.find_op_in_row(&Pos { x, y }, 0..5, forward);
+        let find_col = |x, y, forward| codebox.find_op_in_col(&Pos { x, y }, 0..3, forward);
+
+        assert_eq!(find_row(1, 0, true), Some(Pos { x: 3, y: 0 }));
+        assert_eq!(find_row(4, 0, true), Some(Pos { x: 0, y: 0 }));
+        assert_eq!(find_row(2, 0, false), Some(Pos { x: 0, y: 0 }));
+        assert_eq!(find_row(0, 0, false), Some(Pos { x: 0, y: 0 }));
+        assert_eq!(find_row(2, 1, true), None);
+
+        assert_eq!(find_col(2, 1, true), Some(Pos { x: 2, y: 2 }));
+        assert_eq!(find_col(2, 1, false), Some(Pos { x: 2, y: 2 }));
+        assert_eq!(find_col(1, 1, false), None);
+
+        let bounded = codebox.find_op_in_row(&Pos { x: 1, y: 0 }, 1..3, true);
+        assert_eq!(bounded, None);
+        let bounded = codebox.find_op_in_row(&Pos { x: 1, y: 0 }, 1..4, false);
+        assert_eq!(bounded, Some(Pos { x: 3, y: 0 }));
+    }
+
+    #[test]
+    fn test_validate() {
+        let all: String = OPS.iter().map(|(_, ops)| *ops).collect();
+        assert_eq!(Codebox::new(&all).validate(), Ok(()));
+        assert_eq!(Codebox::new("\"hello\"ooooo;\n'w\"' ;").validate(), Ok(()));
+        assert_eq!(
+            Codebox::new("1j;\n \"ab\"h\n\"xy").validate(),
+            Err(vec![(Pos { x: 1, y: 0 }, 'j'), (Pos { x: 5, y: 1 }, 'h')])
+        );
+    }
+
+    #[test]
+    fn test_distinct_ops() {
+        let mut codebox = Codebox::new(
+            "0voa                            ~/?=0:\\
+ voa            oooo'Buzz'~<     /
+ >1+:aa*1+=?;::5%:{3%:@*?\\?/'zziF'oooo/
+ ^oa                 n:~~/",
+        );
+        let ops = codebox.distinct_ops();
+        for chr in ['+', '?', 'o', 'n', ';', 'B', 'F'].iter() {
+            assert!(ops.contains(chr));
+        }
+        for chr in ['-', ',', 'g', 'p', 'x', '.'].iter() {
+            assert!(!ops.contains(chr));
+        }
+
+        codebox.set_instruction(Pos { x: 5, y: 0 }, 'x');
+        assert!(codebox.distinct_ops().contains(&'x'));
+        assert!(Codebox::new("  \n ").distinct_ops().is_empty());
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(Codebox::new("1n\n;").render(), "1n\n; ");
+        assert_eq!(Codebox::new("").render(), "");
+    }
+
+    #[test]
+    fn test_render_with_pointer() {
+        let codebox = Codebox::new("1 n\n;");
+        assert_eq!(
+            codebox.render_with_pointer(&Pos { x: 2, y: 0 }),
+            "1 \x1b[7mn\x1b[0m\n;  "
+        );
+        assert_eq!(
+            Codebox::new("1n\n;").render_with_pointer(&Pos { x: 0, y: 0 }),
+            "\x1b[7m1\x1b[0mn\n; "
+        );
+    }
 }
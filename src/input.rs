@@ -0,0 +1,50 @@
+/// Wraps an input stream, passing on only the characters that match a
+/// predicate. This lets `i` skip over characters a program doesn't care
+/// about, such as whitespace between numbers.
+pub struct FilterCharIter<I, P> {
+    inner: I,
+    predicate: P,
+}
+
+impl<I, P> FilterCharIter<I, P>
+where
+    I: Iterator<Item = char>,
+    P: FnMut(char) -> bool,
+{
+    pub fn new(inner: I, predicate: P) -> Self {
+        Self { inner, predicate }
+    }
+}
+
+impl<I, P> Iterator for FilterCharIter<I, P>
+where
+    I: Iterator<Item = char>,
+    P: FnMut(char) -> bool,
+{
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let predicate = &mut self.predicate;
+        self.inner.find(|&chr| predicate(chr))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::test::SharedOutput;
+    use crate::Interpreter;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_skips_non_matching() {
+        let input = FilterCharIter::new("a b  c".chars(), |chr| chr != ' ');
+        let output = Rc::new(RefCell::new(String::new()));
+        let mut interpreter =
+            Interpreter::new("iii ooo;", input).with_output(SharedOutput(Rc::clone(&output)));
+
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "cba");
+    }
+}
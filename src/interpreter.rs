@@ -1,42 +1,189 @@
-use crate::codebox::{Codebox, Instruction, Pos};
+use crate::codebox::{is_valid_op, Codebox, Instruction, Pos, OPS};
 use crate::stack::{ProgramStack, StackError};
 
 use rand::{
     distributions::{Distribution, Standard},
-    Rng,
+    rngs::StdRng,
+    Rng, SeedableRng,
 };
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use std::io::{stdout, Write};
+use std::hash::{Hash, Hasher};
+use std::io::{self, stdout, Write};
+use std::ops::Range;
 
-#[derive(Debug, PartialEq)]
-enum Direction {
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Direction {
     North,
     East,
     South,
     West,
 }
 
-#[derive(Debug, PartialEq)]
-enum State {
+/// The kinds of instruction, grouped as in the ><> spec.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum OpCategory {
+    /// Digits, and characters pushed while parsing a string.
+    Literal,
+    Arithmetic,
+    Comparison,
+    StackManipulation,
+    Trampoline,
+    Movement,
+    InputOutput,
+    CodeboxManipulation,
+    /// `;`, and anything that isn't a valid instruction.
+    Other,
+}
+
+/// What `i` does once the input runs out.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum EofBehavior {
+    PushNegativeOne,
+    PushValue(f64),
+    /// End the program, as if by `;`.
+    Halt,
+}
+
+/// Which handles an op that is both a core instruction and registered with
+/// `Interpreter::with_op`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ExtensionMode {
+    /// Core instructions always keep their meaning; registered handlers only
+    /// run for other characters.
+    CoreFirst,
+    /// Registered handlers take precedence, so core instructions can be
+    /// remapped.
+    ExtensionFirst,
+}
+
+/// Counts of what a program has done so far, for profiling.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecutionStats {
+    pub steps: usize,
+    /// The number of ops executed in each category, indexed as in
+    /// `OpCategory::ALL`.
+    pub ops_by_category: [usize; OpCategory::ALL.len()],
+    /// Whether `p` has changed the codebox.
+    pub self_modified: bool,
+    /// The most entries the stack has held at once, across all substacks.
+    pub peak_stack_depth: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
     Running,
     Done,
+    /// Execution hit an error while breaking on errors, and is suspended so
+    /// that the interpreter can be inspected.
+    Errored(RuntimeError),
+    /// The pointer is on an `i` but no input has arrived yet. Execution picks
+    /// up from the `i` once input is fed or closed. Only reached with
+    /// `Interpreter::with_blocking_input`.
+    AwaitingInput,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 enum ParseMode {
     Normal,
     Text(char),
 }
 
-#[derive(Debug)]
+/// The change an instruction made to the stack, as seen across the base stack
+/// and all substacks. `popped` and `pushed` are each ordered bottom first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackDelta {
+    pub pushed: Vec<f64>,
+    pub popped: Vec<f64>,
+}
+
+/// What a single successful step did, as returned by `try_step`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepInfo {
+    /// Where the pointer was when the step started.
+    pub pos: Pos,
+    pub instr: Instruction,
+    pub delta: StackDelta,
+}
+
+/// A copy of everything that determines how a program will continue: the
+/// codebox, stack, pointer, direction, parse mode and execution state.
+/// Snapshots can be hashed, so repeated states can be found by collecting them
+/// in a set, and can be handed back to `Interpreter::restore`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterpreterState {
+    codebox: Codebox,
+    stack: ProgramStack,
+    ptr: Pos,
+    dir: Direction,
+    mode: ParseMode,
+    state: State,
+}
+
+impl Eq for InterpreterState {}
+
+// the execution state is left out of the hash since errors can hold floats;
+// equal snapshots still hash the same
+impl Hash for InterpreterState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.codebox.hash(state);
+        self.stack.hash(state);
+        self.ptr.hash(state);
+        self.dir.hash(state);
+        self.mode.hash(state);
+    }
+}
+
+/// Something that happened during execution, as reported to an event sink.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The pointer is about to execute the instruction at `pos`.
+    Step { pos: Pos, instr: Instruction },
+    /// The program wrote some output.
+    Output(String),
+    /// A step failed.
+    Error(RuntimeError),
+    /// The program finished with values left on its stacks, listed bottom
+    /// first. Only reported when enabled with
+    /// `Interpreter::with_stack_imbalance_warning`.
+    StackImbalance(Vec<f64>),
+    /// The program finished.
+    Terminated,
+}
+
+type VerboseTraceHook = Box<dyn FnMut(char, Pos, &StackDelta)>;
+type NumberFormatter = Box<dyn Fn(f64) -> String>;
+type OpHandler = Box<dyn FnMut(&mut ProgramStack) -> Result<(), RuntimeError>>;
+type TraceHook = Box<dyn FnMut(Pos, char, &Direction)>;
+type TerminateHook<T> = Box<dyn FnMut(&Interpreter<T>)>;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum RuntimeError {
-    InvalidInstruction(char),
-    UnimplementedInstruction(char),
+    // the variants below that carry a `Pos` report where the pointer was
+    // when the instruction failed
+    InvalidInstruction(char, Pos),
+    UnimplementedInstruction(char, Pos),
     InvalidPosition(f64, f64),
-    CharConversionFailure,
-    StackError(StackError),
+    CharConversionFailure(f64, Pos),
+    StackError(StackError, Pos),
     UnexpectedEOF,
+    OutputLimitExceeded,
+    NonFiniteOutput(f64),
+    NonFiniteStackValue(f64),
+    UnterminatedString(Pos),
+    /// The pointer ran off an edge that doesn't wrap, leaving `Pos` heading in
+    /// `Direction`.
+    OutOfBounds(Pos, Direction),
+    /// Writing to the output failed.
+    OutputError(io::ErrorKind),
+    StepLimitExceeded,
+    PokeLimitExceeded,
+    JumpLimitExceeded,
+    /// The pointer, at `Pos` heading in `Direction`, went all the way around
+    /// its row or column without finding an op, and would loop forever.
+    NoInstructionsReachable(Pos, Direction),
 }
 pub struct Interpreter<T: Iterator<Item = char>> {
     codebox: Codebox,
@@ -45,379 +192,3511 @@ pub struct Interpreter<T: Iterator<Item = char>> {
     dir: Direction,
     state: State,
     mode: ParseMode,
+    text_start: Option<Pos>,
 
     input_stream: T,
-    output: Box<dyn Fn(String)>,
+    output: Box<dyn Write>,
+    output_len: usize,
+    max_output: Option<usize>,
+    eof_behavior: EofBehavior,
+    history: Option<Vec<Pos>>,
+    epsilon: f64,
+    number_separator: Option<String>,
+    last_output_was_number: bool,
+    verbose_trace: Option<VerboseTraceHook>,
+    break_on_error: bool,
+    warn_stack_imbalance: bool,
+    recent_output: Option<String>,
+    blank_cell_value: f64,
+    region: Option<(Pos, Pos)>,
+    strict_numbers: bool,
+    non_finite_names: (String, String),
+    programs: HashMap<String, Codebox>,
+    // hashes of every state seen, see `state_hash`
+    seen_states: Option<HashSet<u64>>,
+    // whether `x` has run since the last reset, after which repeated states
+    // no longer mean the program is stuck
+    used_random: bool,
+    event_sink: Option<Box<dyn FnMut(Event)>>,
+    strict_strings: bool,
+    display_precision: Option<usize>,
+    stats: ExecutionStats,
+    relative_jump: Option<char>,
+    wrap: (bool, bool),
+    // set while running a fragment, which ends rather than leaving its edge
+    finish_at_edge: bool,
+    number_input: Option<char>,
+    // characters read ahead of time by number input, or put back by a failed
+    // `try_step`, to be returned by the next reads
+    pending_input: VecDeque<char>,
+    // characters taken from the input during a `try_step`, to put back if it
+    // fails
+    step_input: Option<Vec<char>>,
+    output_buffer: Option<(String, usize)>,
+    consumed_input: Option<Vec<char>>,
+    coverage: Option<HashSet<Pos>>,
+    step_limit: Option<u64>,
+    output_rounding: Option<f64>,
+    number_formatter: Option<NumberFormatter>,
+    rng: StdRng,
+    canonicalize_zero: bool,
+    on_terminate: Option<TerminateHook<T>>,
+    // whether `on_terminate` has fired for the current run
+    terminated: bool,
+    pokes: usize,
+    max_pokes: Option<usize>,
+    jumps: usize,
+    max_jumps: Option<usize>,
+    trace_hook: Option<TraceHook>,
+    blocking_input: bool,
+    fed_input: VecDeque<char>,
+    input_closed: bool,
+    extension_ops: HashMap<char, OpHandler>,
+    extension_mode: ExtensionMode,
 }
 
 impl<T: Iterator<Item = char>> Interpreter<T> {
     pub fn new(code: &str, input_stream: T) -> Self {
+        Self::from_codebox(Codebox::new(code), input_stream)
+    }
+
+    /// Like `new`, but checks the program with `Codebox::validate` first,
+    /// returning every invalid character instead of failing only once the
+    /// pointer reaches one. Ops added later with `with_op`,
+    /// `with_relative_jump` or `with_number_input` can't be known yet, so
+    /// programs using them should be created with `new`.
+    pub fn new_strict(code: &str, input_stream: T) -> Result<Self, Vec<(Pos, char)>> {
+        let codebox = Codebox::new(code);
+        codebox.validate()?;
+        Ok(Self::from_codebox(codebox, input_stream))
+    }
+
+    /// Creates an interpreter for a program given as rows of cells, as
+    /// `Codebox::from_grid` does.
+    pub fn from_grid(grid: Vec<Vec<char>>, input_stream: T) -> Self {
+        Self::from_codebox(Codebox::from_grid(grid), input_stream)
+    }
+
+    /// Creates an interpreter for an already-built codebox.
+    pub fn from_codebox(codebox: Codebox, input_stream: T) -> Self {
         Self {
-            codebox: Codebox::new(code),
+            codebox,
             stack: ProgramStack::new(),
             input_stream,
             ptr: Pos { x: 0, y: 0 },
             dir: Direction::East,
             state: State::Running,
             mode: ParseMode::Normal,
-            output: Box::new(|s| {
-                print!("{}", s);
-                stdout().flush().expect("Failed to flush stdout");
-            }),
+            text_start: None,
+            output: Box::new(stdout()),
+            output_len: 0,
+            max_output: None,
+            eof_behavior: EofBehavior::PushNegativeOne,
+            history: None,
+            epsilon: f64::EPSILON,
+            number_separator: None,
+            last_output_was_number: false,
+            verbose_trace: None,
+            break_on_error: false,
+            warn_stack_imbalance: false,
+            recent_output: None,
+            blank_cell_value: 0f64,
+            region: None,
+            strict_numbers: false,
+            non_finite_names: ("NaN".to_string(), "inf".to_string()),
+            programs: HashMap::new(),
+            seen_states: None,
+            used_random: false,
+            event_sink: None,
+            strict_strings: false,
+            display_precision: None,
+            stats: ExecutionStats::default(),
+            relative_jump: None,
+            wrap: (true, true),
+            finish_at_edge: false,
+            number_input: None,
+            pending_input: VecDeque::new(),
+            step_input: None,
+            output_buffer: None,
+            consumed_input: None,
+            coverage: None,
+            step_limit: None,
+            output_rounding: None,
+            number_formatter: None,
+            rng: StdRng::from_entropy(),
+            canonicalize_zero: false,
+            on_terminate: None,
+            terminated: false,
+            pokes: 0,
+            max_pokes: None,
+            jumps: 0,
+            max_jumps: None,
+            trace_hook: None,
+            blocking_input: false,
+            fed_input: VecDeque::new(),
+            input_closed: false,
+            extension_ops: HashMap::new(),
+            extension_mode: ExtensionMode::CoreFirst,
         }
     }
 
-    pub fn run(&mut self) {
-        if let Ok(_) = self.run_to_end() {
-            println!();
-        } else {
-            println!("something smells fishy...");
-        }
+    /// Pushes `values` onto the base stack, in order, before execution starts.
+    pub fn with_initial_stack(mut self, values: Vec<f64>) -> Self {
+        self.stack.top().extend(values);
+        self
     }
 
-    pub fn run_to_end(&mut self) -> Result<(), RuntimeError> {
-        while self.state != State::Done {
-            self.step()?;
+    /// Like `with_initial_stack`, but rejects NaN and infinite values instead
+    /// of seeding the program with them.
+    pub fn try_with_initial_stack(self, values: Vec<f64>) -> Result<Self, RuntimeError> {
+        if let Some(&val) = values.iter().find(|val| !val.is_finite()) {
+            return Err(RuntimeError::NonFiniteStackValue(val));
         }
-        Ok(())
+        Ok(self.with_initial_stack(values))
     }
 
-    fn step(&mut self) -> Result<(), RuntimeError> {
-        let instr = self.codebox.get_instruction(&self.ptr);
-        if let Instruction::Op(instr) = instr {
-            self.execute_instruction(instr)?;
-        } else if let ParseMode::Text(_) = self.mode {
-            self.push_char(' ');
-        }
-        self.move_to_next();
-        Ok(())
+    /// Limits the total number of bytes the program may output. Once the limit
+    /// would be exceeded, execution stops with `OutputLimitExceeded`.
+    pub fn with_max_output(mut self, bytes: usize) -> Self {
+        self.max_output = Some(bytes);
+        self
     }
 
-    fn execute_instruction(&mut self, instr: char) -> Result<(), RuntimeError> {
-        if let ParseMode::Text(quote_type) = self.mode {
-            if instr != quote_type {
-                self.push_char(instr);
-                return Ok(());
-            }
-        }
-
-        match instr {
-            // literals
-            '0'..='9' | 'a'..='f' => self.push_num(instr),
+    /// Limits the number of times the program may modify itself with `p`, to
+    /// bound the cost of untrusted code rewriting the codebox. Once the limit
+    /// is reached, further `p`s fail with `PokeLimitExceeded`.
+    pub fn with_max_pokes(mut self, count: usize) -> Self {
+        self.max_pokes = Some(count);
+        self
+    }
 
-            // maths
-            '+' => self.stack.top().add()?,
-            '-' => self.stack.top().subtract()?,
-            '*' => self.stack.top().multiply()?,
-            ',' => self.stack.top().divide()?,
-            '%' => self.stack.top().modulo()?,
+    /// Limits the number of times the program may jump with `.`, which is a
+    /// more targeted guard than a step limit for programs that loop by
+    /// jumping. Once the limit is reached, further `.`s fail with
+    /// `JumpLimitExceeded`.
+    pub fn with_max_jumps(mut self, count: usize) -> Self {
+        self.max_jumps = Some(count);
+        self
+    }
 
-            // comparisons
-            '=' => self.stack.top().equals()?,
-            ')' => self.stack.top().greater_than()?,
-            '(' => self.stack.top().less_than()?,
+    /// Sets the value `i` pushes once the input stream is exhausted (`-1` by
+    /// default).
+    pub fn with_eof_value(mut self, eof_value: f64) -> Self {
+        self.set_eof_behavior(EofBehavior::PushValue(eof_value));
+        self
+    }
 
-            // stack manipulation
-            ':' => self.stack.top().dup()?,
-            '~' => {
-                self.stack.top().pop()?;
-            }
-            '$' => self.stack.top().swap(2)?,
-            '@' => self.stack.top().swap(3)?,
-            '}' => self.stack.top().shift_right(),
-            '{' => self.stack.top().shift_left(),
-            '[' => self.stack.split_stack()?,
-            ']' => self.stack.drop_stack(),
-            'l' => self.stack.top().push_len(),
-            'r' => self.stack.top().reverse(),
-            '&' => self.stack.top().swap_register()?,
+    /// Sets what `i`, and number input, do once the input stream is
+    /// exhausted.
+    pub fn set_eof_behavior(&mut self, behavior: EofBehavior) {
+        self.eof_behavior = behavior;
+    }
 
-            // trampolines
-            '!' => self.move_to_next(),
-            '?' => {
-                if self.stack.top().pop()? == 0f64 {
-                    self.move_to_next();
-                }
-            }
+    /// Restricts execution to the rectangle with the given corners (inclusive),
+    /// starting at `top_left`. The pointer wraps around within the region
+    /// rather than the whole codebox.
+    pub fn with_region(mut self, top_left: Pos, bottom_right: Pos) -> Self {
+        let corners = (
+            Pos {
+                x: top_left.x.min(bottom_right.x),
+                y: top_left.y.min(bottom_right.y),
+            },
+            Pos {
+                x: top_left.x.max(bottom_right.x),
+                y: top_left.y.max(bottom_right.y),
+            },
+        );
+        self.region = Some(corners);
+        self.ptr = self.start_pos();
+        self
+    }
 
-            // directions
-            '^' => self.dir = Direction::North,
-            '>' => self.dir = Direction::East,
-            'v' => self.dir = Direction::South,
-            '<' => self.dir = Direction::West,
+    /// Makes `n` print values within `epsilon` of an integer as that integer,
+    /// hiding floating point error such as `2.9999999999`.
+    pub fn with_output_rounding(mut self, epsilon: f64) -> Self {
+        self.output_rounding = Some(epsilon);
+        self
+    }
 
-            // mirrors
-            '/' => {
-                self.dir = match self.dir {
-                    Direction::North => Direction::East,
-                    Direction::East => Direction::North,
-                    Direction::South => Direction::West,
-                    Direction::West => Direction::South,
-                }
-            }
-            '\\' => {
-                self.dir = match self.dir {
-                    Direction::North => Direction::West,
-                    Direction::East => Direction::South,
-                    Direction::South => Direction::East,
-                    Direction::West => Direction::North,
-                }
-            }
-            '|' => {
-                if self.dir == Direction::West || self.dir == Direction::East {
-                    self.dir = self.dir.reverse();
-                }
-            }
-            '_' => {
-                if self.dir == Direction::North || self.dir == Direction::North {
-                    self.dir = self.dir.reverse();
-                }
-            }
-            '#' => self.dir = self.dir.reverse(),
-            'x' => self.dir = rand::random(),
-            '.' => self.ptr = self.load_pos()?,
+    /// Sets how `n` prints NaN and infinity when not in strict mode.
+    /// Negative infinity is printed as `infinity` with a leading `-`.
+    pub fn with_non_finite_names(mut self, nan: &str, infinity: &str) -> Self {
+        self.non_finite_names = (nan.to_string(), infinity.to_string());
+        self
+    }
 
-            // input/output
-            '"' | '\'' => self.switch_parse_mode(instr),
-            'n' => (*self.output)(format!("{}", self.stack.top().pop()?)),
-            'o' => {
-                let ch = self.stack.top().pop()?;
-                self.print_char(ch)?;
-            }
-            'i' => match self.input_stream.next() {
-                None => self.stack.top().push(-1f64),
-                Some(chr) => self.push_char(chr),
-            },
+    /// Limits the number of steps the program may take, to guard against
+    /// programs that never end. Once the limit is reached, further steps fail
+    /// with `StepLimitExceeded`.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
 
-            // codebox manipulation
-            'g' => {
-                let pos = self.load_pos()?;
-                if let Instruction::Op(xy_instr) = self.codebox.get_instruction(&pos) {
-                    self.push_char(xy_instr);
-                } else {
-                    self.stack.top().push(0f64);
-                }
-            }
-            'p' => {
-                let pos = self.load_pos()?;
-                let instr = f64_to_char(self.stack.top().pop()?)?;
-                self.codebox.set_instruction(pos, instr);
-            }
+    /// Calls `hook` right before each op executes, with its position, the op
+    /// and the pointer's direction. Blank cells don't count as ops.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
 
-            // end
-            ';' => self.state = State::Done,
+    /// In strict mode, outputting NaN or infinity with `n` is an error, as is
+    /// arithmetic that gives either of them.
+    pub fn set_strict_numbers(&mut self, strict: bool) {
+        self.strict_numbers = strict;
+    }
 
-            // yet to be implemented
-            // ... none?
+    /// Sets the value `g` pushes when reading a blank cell (`0` by default).
+    pub fn with_blank_cell_value(mut self, value: f64) -> Self {
+        self.blank_cell_value = value;
+        self
+    }
 
-            // everything else
-            _ => Err(RuntimeError::InvalidInstruction(instr))?,
-        }
-        Ok(())
+    /// Sets the tolerance `=` uses when comparing values, applied both
+    /// absolutely and relative to the larger magnitude.
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
     }
 
-    fn move_to_next(&mut self) {
-        self.ptr = self.get_next_pos();
+    /// Limits stack values shown in diagnostics, such as `render_stack` and
+    /// `diagnose`, to `digits` decimal places. Program output is unaffected.
+    pub fn with_display_precision(mut self, digits: usize) -> Self {
+        self.display_precision = Some(digits);
+        self
+    }
 
-        // in text mode, noops can't be skipped
-        if self.mode == ParseMode::Normal {
-            while self.codebox.get_instruction(&self.ptr) == Instruction::Noop {
-                self.ptr = self.get_next_pos();
-            }
-        }
+    /// Makes `op`, which should not already be an instruction, pop `dx` and
+    /// `dy` and jump by that offset from the current position, wrapping around
+    /// the codebox. Like `.`, execution continues after the landing cell.
+    pub fn with_relative_jump(mut self, op: char) -> Self {
+        self.relative_jump = Some(op);
+        self
     }
 
-    fn get_next_pos(&self) -> Pos {
-        let Pos { x, y } = self.ptr;
-        match self.dir {
-            Direction::North => Pos {
-                y: get_wrapped_coord(y, -1, self.codebox.height()),
-                x,
-            },
-            Direction::East => Pos {
-                y,
-                x: get_wrapped_coord(x, 1, self.codebox.width()),
-            },
-            Direction::South => Pos {
-                y: get_wrapped_coord(y, 1, self.codebox.height()),
-                x,
-            },
-            Direction::West => Pos {
-                y,
-                x: get_wrapped_coord(x, -1, self.codebox.width()),
-            },
-        }
+    /// Makes `op`, which should not already be an instruction, read a whole
+    /// non-negative integer from the input. Leading whitespace is skipped and
+    /// the first character after the digits is left for the next read. If
+    /// there are no digits before the end of the input, the EOF value is
+    /// pushed instead.
+    pub fn with_number_input(mut self, op: char) -> Self {
+        self.number_input = Some(op);
+        self
     }
 
-    fn push_num(&mut self, chr: char) {
-        self.stack.top().push(chr.to_digit(16).unwrap() as f64);
+    /// Holds output back until at least `capacity` bytes have built up, the
+    /// program ends or fails, or `flush_output` is called.
+    pub fn with_output_buffer(mut self, capacity: usize) -> Self {
+        self.output_buffer = Some((String::with_capacity(capacity), capacity));
+        self
     }
 
-    fn push_char(&mut self, chr: char) {
-        self.stack.top().push((chr as u32) as f64);
+    /// Records every character read from the input stream, for later
+    /// inspection via `consumed_input`.
+    pub fn with_input_recording(mut self) -> Self {
+        self.consumed_input = Some(vec![]);
+        self
     }
 
-    fn switch_parse_mode(&mut self, quote_type: char) {
-        self.mode = if self.mode == ParseMode::Normal {
-            ParseMode::Text(quote_type)
-        } else {
-            ParseMode::Normal
-        }
+    /// Sets whether the pointer wraps around the left and right edges
+    /// (`horizontal`) and the top and bottom edges (`vertical`). Both wrap by
+    /// default; running off an edge that doesn't wrap fails with
+    /// `OutOfBounds`.
+    pub fn with_wrap(mut self, horizontal: bool, vertical: bool) -> Self {
+        self.wrap = (horizontal, vertical);
+        self
     }
 
-    fn load_pos(&mut self) -> Result<Pos, RuntimeError> {
-        let y = self.stack.top().pop()?;
-        let x = self.stack.top().pop()?;
-        if x < 0f64 || y < 0f64 || x != x.trunc() || y != y.trunc() {
-            Err(RuntimeError::InvalidPosition(x, y))?
-        } else {
-            Ok(Pos {
-                x: x as usize,
-                y: y as usize,
-            })
-        }
+    /// Sends all program output to `output` rather than stdout. Output is
+    /// flushed as it's written, so that it appears promptly; use
+    /// `with_output_buffer` to write in larger chunks.
+    pub fn with_output<W: Write + 'static>(mut self, output: W) -> Self {
+        self.output = Box::new(output);
+        self
     }
 
-    fn print_char(&self, chr: f64) -> Result<(), RuntimeError> {
-        let chr = f64_to_char(chr)?;
-        (*self.output)(format!("{}", chr as char));
-        Ok(())
+    /// Registers `handler` to run when the pointer executes `op`, letting
+    /// embedders add instructions of their own. Whether a handler for a core
+    /// instruction runs is decided by the extension mode.
+    pub fn with_op(mut self, op: char, handler: OpHandler) -> Self {
+        self.extension_ops.insert(op, handler);
+        self
     }
-}
 
-fn get_wrapped_coord(coord: usize, incr: isize, max: usize) -> usize {
-    let coord = coord as isize;
-    if coord == 0 && incr < 0 {
-        max - 1
-    } else if coord + incr >= max as isize {
-        0
-    } else {
-        (coord + incr) as usize
+    /// Sets whether ops registered with `with_op` can override core
+    /// instructions (`ExtensionMode::CoreFirst`, so they can't, by default).
+    pub fn with_extension_mode(mut self, mode: ExtensionMode) -> Self {
+        self.extension_mode = mode;
+        self
     }
-}
 
-fn f64_to_char(chr: f64) -> Result<char, RuntimeError> {
-    if chr < u32::min_value() as f64 || chr > u32::max_value() as f64 || chr != chr.trunc() {
-        return Err(RuntimeError::CharConversionFailure);
+    /// Suspends the program in `State::AwaitingInput` when `i` finds no input,
+    /// rather than pushing the EOF value, until more input is given with
+    /// `feed_input` or the input is ended with `close_input`. Fed input is
+    /// read once the input stream runs out.
+    pub fn with_blocking_input(mut self) -> Self {
+        self.blocking_input = true;
+        self
     }
-    std::char::from_u32(chr as u32).ok_or(RuntimeError::CharConversionFailure)
-}
 
-impl Direction {
-    pub fn reverse(&self) -> Direction {
-        match self {
-            Direction::North => Direction::South,
-            Direction::East => Direction::West,
-            Direction::South => Direction::North,
-            Direction::West => Direction::East,
+    /// Adds `input` to be read once the input stream runs out, resuming the
+    /// program if it was waiting for input.
+    pub fn feed_input(&mut self, input: &str) {
+        self.fed_input.extend(input.chars());
+        if self.state == State::AwaitingInput {
+            self.state = State::Running;
         }
     }
-}
 
-impl Distribution<Direction> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Direction {
-        match rng.gen_range(0, 4) {
-            0 => Direction::North,
-            1 => Direction::East,
-            2 => Direction::South,
-            _ => Direction::West,
+    /// Marks the end of the input, so that `i` pushes the EOF value rather
+    /// than waiting once all fed input has been read.
+    pub fn close_input(&mut self) {
+        self.input_closed = true;
+        if self.state == State::AwaitingInput {
+            self.state = State::Running;
         }
     }
-}
 
-impl<T: Iterator<Item = char>> std::fmt::Debug for Interpreter<T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        f.debug_struct("Interpreter")
-            .field("codebox", &self.codebox)
-            .field("stack", &self.stack)
-            .field("ptr", &self.ptr)
-            .field("dir", &self.dir)
-            .field("state", &self.state)
-            .field("mode", &self.mode)
-            .finish()
+    /// Calls `hook` with the final state of the interpreter when `run_to_end`
+    /// stops, whether the program finished or failed. It fires once per run:
+    /// calling `run_to_end` again on a program that has already stopped, such
+    /// as one suspended by `with_break_on_error`, doesn't fire it again.
+    pub fn with_on_terminate(mut self, hook: TerminateHook<T>) -> Self {
+        self.on_terminate = Some(hook);
+        self
     }
-}
 
-impl Display for RuntimeError {
-    fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{:?}", self)
+    /// Replaces `-0` results of arithmetic with `0`, so they print without a
+    /// sign.
+    pub fn with_canonical_zero(mut self) -> Self {
+        self.canonicalize_zero = true;
+        self
     }
-}
 
-impl Error for RuntimeError {
-    fn description(&self) -> &str {
-        "" // TODO
+    /// Seeds the generator `x` picks directions with, so that runs with the
+    /// same seed move identically.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
     }
-}
 
-impl From<StackError> for RuntimeError {
-    fn from(error: StackError) -> Self {
-        RuntimeError::StackError(error)
+    /// Sets a separator to emit between consecutive `n` outputs, which are
+    /// otherwise concatenated.
+    pub fn with_number_separator(mut self, separator: Option<String>) -> Self {
+        self.number_separator = separator;
+        self
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::Interpreter;
-    use std::iter::empty;
+    /// Formats the numbers output by `n` with `formatter` instead of the
+    /// default formatting, which includes rounding and the names given to
+    /// non-finite values. Separators and strict numbers still apply.
+    pub fn with_number_formatter(mut self, formatter: NumberFormatter) -> Self {
+        self.number_formatter = Some(formatter);
+        self
+    }
 
-    #[test]
-    fn test_helloworld() {
-        let mut interpreter = Interpreter::new(
-            "\"hello, world\"rv
-          o;!?l<",
-            empty(),
-        );
+    /// Calls `hook` after every executed op with the op, its position and how
+    /// it changed the stack.
+    pub fn with_verbose_trace<F: FnMut(char, Pos, &StackDelta) + 'static>(
+        mut self,
+        hook: F,
+    ) -> Self {
+        self.verbose_trace = Some(Box::new(hook));
+        self
+    }
 
-        let res = interpreter.run_to_end();
-        if res.is_err() {
-            println!();
-            println!("{:#?}", interpreter);
-        }
-        println!();
+    /// Ends the program, as if by `;`, as soon as it reaches a state it has
+    /// been in before, since from then on it can only repeat itself. Input is
+    /// not part of the state, so programs that read input may be ended early.
+    /// Detection stops once `x` has run, since a random direction means a
+    /// repeated state can lead somewhere new. States are remembered by their
+    /// hashes, so a hash collision could in principle end a program early.
+    /// Every distinct state adds a hash to the set, so memory use grows with
+    /// the number of steps for as long as the program doesn't repeat itself;
+    /// pair this with a step limit for programs that may run for a long time.
+    pub fn with_halt_on_cycle(mut self) -> Self {
+        self.seen_states = Some(HashSet::new());
+        self
     }
 
-    #[test]
-    fn test_fizzbuzz() {
-        let mut interpreter = Interpreter::new(
-            "0voa                            ~/?=0:\\
- voa            oooo'Buzz'~<     /
- >1+:aa*1+=?;::5%:{3%:@*?\\?/'zziF'oooo/
- ^oa                 n:~~/",
-            empty(),
-        );
+    /// Suspends execution when an instruction fails, leaving the interpreter
+    /// in `State::Errored` rather than `Running` so it can be inspected. Later
+    /// steps return the same error without executing anything.
+    pub fn with_break_on_error(mut self) -> Self {
+        self.break_on_error = true;
+        self
+    }
 
-        let res = interpreter.run_to_end();
-        if res.is_err() {
-            println!();
-            println!("{:#?}", interpreter);
-        }
-        println!();
+    /// Reports an `Event::StackImbalance` to the event sink if the program
+    /// finishes without emptying its stacks, which often points to a logic
+    /// bug.
+    pub fn with_stack_imbalance_warning(mut self) -> Self {
+        self.warn_stack_imbalance = true;
+        self
     }
 
-    #[test]
-    fn test_quine() {
-        let mut interpreter = Interpreter::new("\"r00gol?!;40.", empty());
+    /// Reports every step, output, error and termination to `sink` as it
+    /// happens.
+    pub fn with_event_sink(mut self, sink: Box<dyn FnMut(Event)>) -> Self {
+        self.event_sink = Some(sink);
+        self
+    }
 
-        let res = interpreter.run_to_end();
-        if res.is_err() {
-            println!();
-            println!("{:#?}", interpreter);
-        }
-        println!();
+    /// Treats a string whose pointer wraps back around to its opening quote as
+    /// a mistake, failing with `UnterminatedString` rather than letting the
+    /// quote close its own string.
+    pub fn with_strict_strings(mut self) -> Self {
+        self.strict_strings = true;
+        self
     }
 
-    #[test]
-    fn test_quine2() {
-        let mut interpreter = Interpreter::new(
-            "0>:a$f8+$p1+:5-?vv     
- ^              <>~0v  
-v             <     <  
->0v          ;^?-6:+1~<
+    /// Records the position of every step taken, for later inspection via
+    /// `history` and `frames`.
+    pub fn with_history(mut self) -> Self {
+        self.history = Some(vec![]);
+        self
+    }
+
+    /// Records which cells have been executed, for later inspection via
+    /// `coverage`. Unlike history, this doesn't grow with every step.
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = Some(HashSet::new());
+        self
+    }
+
+    /// Every cell executed so far, if coverage is enabled.
+    pub fn coverage(&self) -> Option<&HashSet<Pos>> {
+        self.coverage.as_ref()
+    }
+
+    /// The top-left and bottom-right corners of the smallest rectangle
+    /// containing every executed cell, or `None` if coverage is disabled or
+    /// nothing has run yet.
+    pub fn coverage_bounds(&self) -> Option<(Pos, Pos)> {
+        let coverage = self.coverage.as_ref()?;
+        let xs = coverage.iter().map(|pos| pos.x);
+        let ys = coverage.iter().map(|pos| pos.y);
+        Some((
+            Pos {
+                x: xs.clone().min()?,
+                y: ys.clone().min()?,
+            },
+            Pos {
+                x: xs.max()?,
+                y: ys.max()?,
+            },
+        ))
+    }
+
+    /// The positions of every step taken so far, if history is enabled.
+    pub fn history(&self) -> Option<&[Pos]> {
+        self.history.as_deref()
+    }
+
+    /// Step and instruction counts for everything run so far.
+    pub fn stats(&self) -> &ExecutionStats {
+        &self.stats
+    }
+
+    /// The most entries the stack has held at once during this run, across
+    /// all substacks.
+    pub fn peak_stack_depth(&self) -> usize {
+        self.stats.peak_stack_depth
+    }
+
+    /// An estimate of the memory held by the codebox and all stacks, in bytes.
+    /// Grows as the program pushes values or writes new cells with `p`.
+    pub fn approx_memory_bytes(&self) -> usize {
+        self.codebox.approx_memory_bytes() + self.stack.approx_memory_bytes()
+    }
+
+    /// Where the string currently being parsed was opened, or `None` outside
+    /// of a string.
+    pub fn text_start_pos(&self) -> Option<Pos> {
+        self.text_start
+    }
+
+    /// Every character read from the input stream so far, if input recording
+    /// is enabled. Feeding these to a new interpreter replays the run.
+    pub fn consumed_input(&self) -> &[char] {
+        self.consumed_input.as_deref().unwrap_or(&[])
+    }
+
+    /// The total number of bytes output so far.
+    pub fn output_len(&self) -> usize {
+        self.output_len
+    }
+
+    /// Renders the grid with the pointer's cell highlighted.
+    pub fn render_with_pointer(&self) -> String {
+        self.codebox.render_with_pointer(&self.ptr)
+    }
+
+    /// Renders the grid as a coverage report, with ops that have run in bold
+    /// and ops that haven't dimmed. Needs `with_coverage`; without it no op
+    /// counts as having run.
+    pub fn coverage_listing(&self) -> String {
+        self.codebox.render_rows(|pos, chr| {
+            let executed = matches!(&self.coverage, Some(coverage) if coverage.contains(&pos));
+            match chr {
+                ' ' => chr.to_string(),
+                _ if executed => format!("\x1b[1m{}\x1b[0m", chr),
+                _ => format!("\x1b[2m{}\x1b[0m", chr),
+            }
+        })
+    }
+
+    /// Renders the grid once per logged step, with the pointer highlighted
+    /// where it was at that step. Note that every frame shows the codebox as
+    /// it is now, so cells changed by `p` appear in their latest form.
+    pub fn frames(&self) -> Vec<String> {
+        self.history()
+            .unwrap_or(&[])
+            .iter()
+            .map(|pos| self.codebox.render_with_pointer(pos))
+            .collect()
+    }
+
+    /// Replaces the program being run and resets execution to its start,
+    /// keeping all configuration.
+    pub fn load_program(&mut self, code: &str) {
+        self.codebox = Codebox::new(code);
+        self.reset();
+    }
+
+    /// Runs `code` as a new program on top of the current stack and register,
+    /// which are kept, as for a calculator that reads one line at a time.
+    /// Rather than wrapping around, the fragment finishes when the pointer
+    /// runs off its edge, or at `;`.
+    pub fn run_fragment(&mut self, code: &str) -> Result<(), RuntimeError> {
+        self.codebox = Codebox::new(code);
+        self.ptr = self.start_pos();
+        self.dir = Direction::East;
+        self.state = State::Running;
+        self.terminated = false;
+        self.mode = ParseMode::Normal;
+        self.text_start = None;
+
+        let wrap = self.wrap;
+        self.wrap = (false, false);
+        self.finish_at_edge = true;
+        let res = self.run_to_end();
+        self.wrap = wrap;
+        self.finish_at_edge = false;
+        res
+    }
+
+    /// Stores a program under `name`, to be run later with `activate`.
+    pub fn load_named(&mut self, name: &str, code: &str) {
+        self.programs.insert(name.to_string(), Codebox::new(code));
+    }
+
+    /// Switches to the program stored under `name`, starting it afresh as
+    /// `load_program` does. Returns false if there is no such program.
+    pub fn activate(&mut self, name: &str) -> bool {
+        match self.programs.get(name) {
+            Some(codebox) => {
+                self.codebox = codebox.clone();
+                self.reset();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.stack.clear_all();
+        self.ptr = self.start_pos();
+        self.dir = Direction::East;
+        self.state = State::Running;
+        self.terminated = false;
+        self.mode = ParseMode::Normal;
+        self.text_start = None;
+        self.stats = ExecutionStats::default();
+        self.pokes = 0;
+        self.jumps = 0;
+        self.used_random = false;
+        self.last_output_was_number = false;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        if let Some(seen_states) = &mut self.seen_states {
+            seen_states.clear();
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.clear();
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn stack(&self) -> &ProgramStack {
+        &self.stack
+    }
+
+    /// The cell the instruction pointer is on.
+    pub fn position(&self) -> Pos {
+        self.ptr
+    }
+
+    /// The direction the instruction pointer will move in on the next step.
+    pub fn direction(&self) -> Direction {
+        self.dir
+    }
+
+    /// The op the pointer will move on to after the current one, assuming the
+    /// current op doesn't turn the pointer or start or end a string. `None`
+    /// if that's a blank cell in a string, or past an edge that doesn't wrap.
+    pub fn next_instruction(&self) -> Option<char> {
+        match self.codebox.get_instruction(&self.next_ptr().ok()?) {
+            Instruction::Op(instr) => Some(instr),
+            Instruction::Noop => None,
+        }
+    }
+
+    /// Moves the instruction pointer to `pos`, which must lie within the
+    /// codebox.
+    pub fn set_position(&mut self, pos: Pos) -> Result<(), RuntimeError> {
+        let (width, height) = self.codebox.extent();
+        if pos.x >= width || pos.y >= height {
+            return Err(RuntimeError::InvalidPosition(pos.x as f64, pos.y as f64));
+        }
+        self.ptr = pos;
+        Ok(())
+    }
+
+    pub fn set_direction(&mut self, dir: Direction) {
+        self.dir = dir;
+    }
+
+    /// The entries of the active stack, bottom first. Stacks below it, made
+    /// by `[`, aren't included.
+    pub fn stack_snapshot(&self) -> Vec<f64> {
+        self.stack.iter_current().copied().collect()
+    }
+
+    pub fn snapshot(&self) -> InterpreterState {
+        InterpreterState {
+            codebox: self.codebox.clone(),
+            stack: self.stack.clone(),
+            ptr: self.ptr,
+            dir: self.dir,
+            mode: self.mode,
+            state: self.state.clone(),
+        }
+    }
+
+    // hashes the same fields as `InterpreterState`, without copying them
+    fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.codebox.hash(&mut hasher);
+        self.stack.hash(&mut hasher);
+        self.ptr.hash(&mut hasher);
+        self.dir.hash(&mut hasher);
+        self.mode.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Puts the interpreter back into a state taken by `snapshot`. Counters,
+    /// input and output are left as they are.
+    pub fn restore(&mut self, state: InterpreterState) {
+        self.codebox = state.codebox;
+        self.stack = state.stack;
+        self.ptr = state.ptr;
+        self.dir = state.dir;
+        self.mode = state.mode;
+        self.state = state.state;
+    }
+
+    /// The current stack, bottom first, formatted for display.
+    pub fn render_stack(&self) -> String {
+        let values: Vec<_> = self
+            .stack
+            .iter_current()
+            .map(|&val| self.display_value(val))
+            .collect();
+        format!("[{}]", values.join(", "))
+    }
+
+    fn display_value(&self, val: f64) -> String {
+        match self.display_precision {
+            Some(digits) if val.is_finite() && val != val.trunc() => {
+                let formatted = format!("{:.*}", digits, val);
+                if formatted.contains('.') {
+                    formatted
+                        .trim_end_matches('0')
+                        .trim_end_matches('.')
+                        .to_string()
+                } else {
+                    formatted
+                }
+            }
+            _ => val.to_string(),
+        }
+    }
+
+    /// Explains `err`, which should have just been returned by `step` or
+    /// `run_to_end`, in terms of the instruction under the pointer and the top
+    /// of the stack.
+    pub fn diagnose(&self, err: &RuntimeError) -> String {
+        const SHOWN_VALUES: usize = 5;
+
+        let Pos { x, y } = self.ptr;
+        let location = match self.codebox.get_instruction(&self.ptr) {
+            // the error already says where it happened
+            Instruction::Op(instr) if err.pos().is_some() => format!("executing '{}'", instr),
+            Instruction::Op(instr) => format!("executing '{}' at ({}, {})", instr, x, y),
+            Instruction::Noop => format!("at blank cell ({}, {})", x, y),
+        };
+
+        let entries: Vec<_> = self.stack.iter_current().collect();
+        let stack = if entries.is_empty() {
+            "the stack is empty".to_string()
+        } else {
+            let shown = &entries[entries.len().saturating_sub(SHOWN_VALUES)..];
+            let values: Vec<_> = shown.iter().map(|&&val| self.display_value(val)).collect();
+            format!(
+                "the top {} of {} stack values are [{}]",
+                shown.len(),
+                entries.len(),
+                values.join(", ")
+            )
+        };
+
+        format!("{} while {}; {}", err, location, stack)
+    }
+
+    pub fn run(&mut self) {
+        match self.run_to_end() {
+            Ok(()) => println!(),
+            Err(err) => println!("{}", self.diagnose(&err)),
+        }
+    }
+
+    pub fn run_to_end(&mut self) -> Result<(), RuntimeError> {
+        if self.state == State::Done {
+            return Ok(());
+        }
+        let mut res = Ok(());
+        while res.is_ok() && self.can_step() {
+            res = self.step().map(drop);
+        }
+        if (res.is_err() || self.state == State::Done) && !self.terminated {
+            self.terminated = true;
+            if let Some(mut hook) = self.on_terminate.take() {
+                hook(self);
+                self.on_terminate = Some(hook);
+            }
+        }
+        res
+    }
+
+    // whether stepping would make progress, rather than the program having
+    // ended or waiting for input
+    fn can_step(&self) -> bool {
+        !matches!(self.state, State::Done | State::AwaitingInput)
+    }
+
+    /// Runs until the pointer reaches one of `breakpoints`, taking at least
+    /// one step first, or until the program ends. Returns whether a
+    /// breakpoint was reached.
+    pub fn run_to_breakpoint(&mut self, breakpoints: &HashSet<Pos>) -> Result<bool, RuntimeError> {
+        while self.can_step() {
+            self.step()?;
+            if self.can_step() && breakpoints.contains(&self.ptr) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Executes the instruction under the pointer and moves to the next one.
+    /// Returns the op executed, or `None` if the pointer was on a blank cell.
+    /// Once the program has ended, or while it waits for input, this does
+    /// nothing and returns `None`.
+    pub fn step(&mut self) -> Result<Option<char>, RuntimeError> {
+        if let State::Errored(err) = &self.state {
+            return Err(err.clone());
+        }
+        if !self.can_step() {
+            return Ok(None);
+        }
+        if self.blocking_input && self.mode == ParseMode::Normal {
+            let waiting = self.codebox.get_instruction(&self.ptr) == Instruction::Op('i')
+                && !self.input_closed
+                && !self.peek_input();
+            if waiting {
+                self.state = State::AwaitingInput;
+                return Ok(None);
+            }
+        }
+        if self.seen_states.is_some() && !self.used_random {
+            let hash = self.state_hash();
+            if let Some(seen_states) = &mut self.seen_states {
+                if !seen_states.insert(hash) {
+                    self.state = State::Done;
+                    self.flush_output()?;
+                    self.emit_termination();
+                    return Ok(None);
+                }
+            }
+        }
+
+        if self.event_sink.is_some() {
+            let instr = self.codebox.get_instruction(&self.ptr);
+            self.emit(Event::Step {
+                pos: self.ptr,
+                instr,
+            });
+        }
+        let mut res = self.execute_step();
+        if res.is_err() || self.state == State::Done {
+            // make sure everything printed before the program stopped is seen
+            let flushed = self.flush_output();
+            res = res.and_then(|executed| flushed.map(|()| executed));
+        }
+        match &res {
+            Err(err) => {
+                self.emit(Event::Error(err.clone()));
+                if self.break_on_error {
+                    self.state = State::Errored(err.clone());
+                }
+            }
+            Ok(_) if self.state == State::Done => self.emit_termination(),
+            Ok(_) => (),
+        }
+        res
+    }
+
+    /// Takes up to `n` steps, stopping early if the program ends or waits for
+    /// input. Returns how many steps were taken.
+    pub fn step_n(&mut self, n: u64) -> Result<u64, RuntimeError> {
+        let mut taken = 0;
+        while taken < n && self.can_step() {
+            self.step()?;
+            taken += 1;
+        }
+        Ok(taken)
+    }
+
+    /// Like `step`, but reports what the step did. If the step fails, the
+    /// stack, pointer, direction, parse mode, codebox, input and random
+    /// number generator are put back as they were before it, so the failing
+    /// instruction can be inspected and retried. The step isn't counted in
+    /// the stats, history, coverage, cycle detection or poke and jump limits
+    /// either. Output already written and events already sent by the
+    /// instruction can't be taken back, and input put back stays in
+    /// `consumed_input`, which records what was taken from the input stream.
+    pub fn try_step(&mut self) -> Result<StepInfo, RuntimeError> {
+        let pos = self.ptr;
+        let instr = self.codebox.get_instruction(&pos);
+        let stack = self.stack.clone();
+        let (dir, mode, text_start) = (self.dir, self.mode, self.text_start);
+        let state = self.state.clone();
+        let stats = self.stats.clone();
+        // `p` is the only op that writes to the codebox, so the (possibly
+        // large) copy is only made for it
+        let codebox = Some(&self.codebox)
+            .filter(|_| instr == Instruction::Op('p'))
+            .cloned();
+        let pending_input = self.pending_input.clone();
+        let (rng, used_random) = (self.rng.clone(), self.used_random);
+        let (pokes, jumps) = (self.pokes, self.jumps);
+        let history_len = self.history.as_ref().map(Vec::len);
+        let newly_covered = matches!(&self.coverage, Some(coverage) if !coverage.contains(&pos));
+        let newly_seen = match &self.seen_states {
+            Some(seen_states) => {
+                let hash = self.state_hash();
+                Some(hash).filter(|hash| !seen_states.contains(hash))
+            }
+            None => None,
+        };
+
+        self.step_input = Some(vec![]);
+        let res = self.step();
+        let read = self.step_input.take().unwrap_or_default();
+        if let Err(err) = res {
+            if let Some(codebox) = codebox {
+                self.codebox = codebox;
+            }
+            self.pending_input = pending_input;
+            self.pending_input.extend(read);
+            self.rng = rng;
+            self.used_random = used_random;
+            self.pokes = pokes;
+            self.jumps = jumps;
+            self.stack = stack;
+            self.ptr = pos;
+            self.dir = dir;
+            self.mode = mode;
+            self.text_start = text_start;
+            self.state = state;
+            self.stats = stats;
+            if let (Some(history), Some(len)) = (&mut self.history, history_len) {
+                history.truncate(len);
+            }
+            if let (Some(coverage), true) = (&mut self.coverage, newly_covered) {
+                coverage.remove(&pos);
+            }
+            if let (Some(seen_states), Some(hash)) = (&mut self.seen_states, newly_seen) {
+                seen_states.remove(&hash);
+            }
+            return Err(err);
+        }
+
+        Ok(StepInfo {
+            pos,
+            instr,
+            delta: StackDelta::between(&stack.flatten(), &self.stack.flatten()),
+        })
+    }
+
+    /// Runs the program to the end, returning everything it output rather
+    /// than sending it to the usual output. Output from earlier steps that is
+    /// still held back by the output buffer is returned too, ahead of the
+    /// rest.
+    pub fn run_to_string(&mut self) -> Result<String, RuntimeError> {
+        let output = std::mem::replace(&mut self.output, Box::new(io::sink()));
+        let held_back = match &mut self.output_buffer {
+            Some((buffer, _)) => std::mem::take(buffer),
+            None => String::new(),
+        };
+        self.recent_output = Some(held_back);
+        let res = self.run_to_end();
+        self.output = output;
+        let captured = self.recent_output.take().unwrap_or_default();
+        res.map(|_| captured)
+    }
+
+    /// Runs until the output produced during this call satisfies `pred`, or
+    /// the program ends. Returns whether `pred` was satisfied.
+    pub fn run_until_output<F: FnMut(&str) -> bool>(
+        &mut self,
+        mut pred: F,
+    ) -> Result<bool, RuntimeError> {
+        self.recent_output = Some(String::new());
+        let mut res = Ok(false);
+        while self.can_step() {
+            if let Err(err) = self.step() {
+                res = Err(err);
+                break;
+            }
+            if pred(self.recent_output.as_deref().unwrap_or("")) {
+                res = Ok(true);
+                break;
+            }
+        }
+        self.recent_output = None;
+        res
+    }
+
+    fn execute_step(&mut self) -> Result<Option<char>, RuntimeError> {
+        if let Some(limit) = self.step_limit {
+            if self.stats.steps as u64 >= limit {
+                return Err(RuntimeError::StepLimitExceeded);
+            }
+        }
+        if let Some(history) = &mut self.history {
+            history.push(self.ptr);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.insert(self.ptr);
+        }
+
+        if self.strict_strings && self.mode != ParseMode::Normal {
+            if let Some(start) = self.text_start.filter(|&start| start == self.ptr) {
+                return Err(RuntimeError::UnterminatedString(start));
+            }
+        }
+
+        self.stats.steps += 1;
+        self.record_stack_depth();
+        let instr = self.codebox.get_instruction(&self.ptr);
+        let category = match (self.mode, instr) {
+            (ParseMode::Text(quote_type), Instruction::Op(instr)) if instr != quote_type => {
+                Some(OpCategory::Literal)
+            }
+            (ParseMode::Text(_), Instruction::Noop) => Some(OpCategory::Literal),
+            (_, Instruction::Op(instr)) => Some(OpCategory::of(instr)),
+            (ParseMode::Normal, Instruction::Noop) => None,
+        };
+        if let Some(category) = category {
+            self.stats.ops_by_category[category as usize] += 1;
+        }
+
+        let executed = if let Instruction::Op(instr) = instr {
+            let pos = self.ptr;
+            if let Some(hook) = &mut self.trace_hook {
+                hook(pos, instr, &self.dir);
+            }
+            let before = self.verbose_trace.as_ref().map(|_| self.stack.flatten());
+            self.execute_instruction(instr).map_err(|err| err.at(pos))?;
+            if let (Some(before), Some(hook)) = (before, &mut self.verbose_trace) {
+                hook(
+                    instr,
+                    pos,
+                    &StackDelta::between(&before, &self.stack.flatten()),
+                );
+            }
+            Some(instr)
+        } else {
+            if let ParseMode::Text(_) = self.mode {
+                self.push_char(' ');
+            }
+            None
+        };
+        self.record_stack_depth();
+        self.move_to_next()?;
+        Ok(executed)
+    }
+
+    fn record_stack_depth(&mut self) {
+        let depth = self.stack.total_len();
+        if depth > self.stats.peak_stack_depth {
+            self.stats.peak_stack_depth = depth;
+        }
+    }
+
+    fn execute_instruction(&mut self, instr: char) -> Result<(), RuntimeError> {
+        if let ParseMode::Text(quote_type) = self.mode {
+            if instr != quote_type {
+                self.push_char(instr);
+                return Ok(());
+            }
+        }
+
+        if let Some(handler) = self.extension_ops.get_mut(&instr) {
+            let core = is_valid_op(instr);
+            if !core || self.extension_mode == ExtensionMode::ExtensionFirst {
+                return handler(&mut self.stack);
+            }
+        }
+
+        match instr {
+            // literals
+            '0'..='9' | 'a'..='f' => self.push_num(instr),
+
+            // maths
+            '+' | '-' | '*' | ',' | '%' => self.arithmetic(instr)?,
+
+            // comparisons
+            '=' => self.stack.top().equals_within(self.epsilon)?,
+            ')' => self.stack.top().greater_than()?,
+            '(' => self.stack.top().less_than()?,
+
+            // stack manipulation
+            ':' => self.stack.top().dup()?,
+            '~' => {
+                self.stack.top().pop()?;
+            }
+            '$' => self.stack.top().swap(2)?,
+            '@' => self.stack.top().swap(3)?,
+            '}' => self.stack.top().shift_right(),
+            '{' => self.stack.top().shift_left(),
+            '[' => self.stack.split_stack()?,
+            ']' => self.stack.drop_stack(),
+            'l' => self.stack.top().push_len(),
+            'r' => self.stack.top().reverse(),
+            '&' => self.stack.top().swap_register()?,
+
+            // trampolines
+            '!' => self.move_to_next()?,
+            '?' => {
+                if self.stack.top().pop()? == 0f64 {
+                    self.move_to_next()?;
+                }
+            }
+
+            // directions and mirrors
+            '^' | '>' | 'v' | '<' | '/' | '\\' | '|' | '_' | '#' => self.dir = self.dir.turn(instr),
+            'x' => {
+                self.dir = self.rng.gen();
+                self.used_random = true;
+            }
+            '.' => {
+                if matches!(self.max_jumps, Some(max_jumps) if self.jumps >= max_jumps) {
+                    return Err(RuntimeError::JumpLimitExceeded);
+                }
+                self.ptr = self.load_pos()?;
+                self.jumps += 1;
+            }
+
+            // input/output
+            '"' | '\'' => self.switch_parse_mode(instr),
+            'n' => {
+                let num = self.stack.top().pop()?;
+                self.print_num(num)?;
+            }
+            'o' => {
+                let ch = self.stack.top().pop()?;
+                self.print_char(ch)?;
+            }
+            'i' => match self.next_input() {
+                None => self.reached_eof(),
+                Some(chr) => self.push_char(chr),
+            },
+
+            // codebox manipulation
+            'g' => {
+                let pos = self.load_pos()?;
+                if let Instruction::Op(xy_instr) = self.codebox.get_instruction(&pos) {
+                    self.push_char(xy_instr);
+                } else {
+                    self.stack.top().push(self.blank_cell_value);
+                }
+            }
+            'p' => {
+                if matches!(self.max_pokes, Some(max_pokes) if self.pokes >= max_pokes) {
+                    return Err(RuntimeError::PokeLimitExceeded);
+                }
+                let pos = self.load_pos()?;
+                let instr = f64_to_char(self.stack.top().pop()?)?;
+                self.codebox.set_instruction(pos, instr);
+                self.pokes += 1;
+                self.stats.self_modified = true;
+            }
+
+            // end
+            ';' => self.state = State::Done,
+
+            // yet to be implemented
+            // ... none?
+
+            // everything else
+            _ if self.relative_jump == Some(instr) => self.jump_relative()?,
+            _ if self.number_input == Some(instr) => self.read_number(),
+            _ => Err(RuntimeError::InvalidInstruction(instr, self.ptr))?,
+        }
+        Ok(())
+    }
+
+    fn move_to_next(&mut self) -> Result<(), RuntimeError> {
+        match self.next_ptr() {
+            Err(RuntimeError::OutOfBounds(_, _)) if self.finish_at_edge => {
+                self.state = State::Done;
+            }
+            next => self.ptr = next?,
+        }
+        Ok(())
+    }
+
+    // where the pointer moves to from its current position and direction
+    fn next_ptr(&self) -> Result<Pos, RuntimeError> {
+        let mut ptr = self.next_pos_in_bounds(self.ptr)?;
+
+        // in text mode, noops can't be skipped
+        if self.mode == ParseMode::Normal && self.codebox.get_instruction(&ptr) == Instruction::Noop
+        {
+            // jump straight over the run of noops where possible
+            if let Some(pos) = self.find_next_op(ptr, self.dir) {
+                ptr = pos;
+            }
+            // a line with no ops at all would otherwise be walked forever
+            let (cols, rows) = self.bounds();
+            let line_len = match self.dir {
+                Direction::East | Direction::West => cols.len(),
+                Direction::North | Direction::South => rows.len(),
+            };
+            let mut moved = 0;
+            while self.codebox.get_instruction(&ptr) == Instruction::Noop {
+                if moved >= line_len {
+                    return Err(RuntimeError::NoInstructionsReachable(self.ptr, self.dir));
+                }
+                ptr = self.next_pos_in_bounds(ptr)?;
+                moved += 1;
+            }
+        }
+        Ok(ptr)
+    }
+
+    fn next_pos_in_bounds(&self, pos: Pos) -> Result<Pos, RuntimeError> {
+        self.get_next_pos(pos, self.dir)
+            .ok_or(RuntimeError::OutOfBounds(pos, self.dir))
+    }
+
+    /// Finds the first op at or after `pos` heading in `dir`, if `pos` is in
+    /// bounds and its line contains any ops.
+    fn find_next_op(&self, pos: Pos, dir: Direction) -> Option<Pos> {
+        let (cols, rows) = self.bounds();
+        if !cols.contains(&pos.x) || !rows.contains(&pos.y) {
+            return None;
+        }
+        let (wrap_x, wrap_y) = self.wrap;
+        // an op found by wrapping around an edge that doesn't wrap can't be
+        // reached
+        match dir {
+            Direction::North => self
+                .codebox
+                .find_op_in_col(&pos, rows, false)
+                .filter(|found| wrap_y || found.y <= pos.y),
+            Direction::East => self
+                .codebox
+                .find_op_in_row(&pos, cols, true)
+                .filter(|found| wrap_x || found.x >= pos.x),
+            Direction::South => self
+                .codebox
+                .find_op_in_col(&pos, rows, true)
+                .filter(|found| wrap_y || found.y >= pos.y),
+            Direction::West => self
+                .codebox
+                .find_op_in_row(&pos, cols, false)
+                .filter(|found| wrap_x || found.x <= pos.x),
+        }
+    }
+
+    /// The cell after `pos` heading in `dir`, or `None` if that would mean
+    /// crossing an edge that doesn't wrap.
+    fn get_next_pos(&self, pos: Pos, dir: Direction) -> Option<Pos> {
+        let Pos { x, y } = pos;
+        let (cols, rows) = self.bounds();
+        let (wrap_x, wrap_y) = self.wrap;
+        Some(match dir {
+            Direction::North => Pos {
+                y: step_coord(y, -1, rows, wrap_y)?,
+                x,
+            },
+            Direction::East => Pos {
+                y,
+                x: step_coord(x, 1, cols, wrap_x)?,
+            },
+            Direction::South => Pos {
+                y: step_coord(y, 1, rows, wrap_y)?,
+                x,
+            },
+            Direction::West => Pos {
+                y,
+                x: step_coord(x, -1, cols, wrap_x)?,
+            },
+        })
+    }
+
+    /// Where execution begins: the origin, or the corner of the region.
+    fn start_pos(&self) -> Pos {
+        self.region
+            .map_or(Pos { x: 0, y: 0 }, |(top_left, _)| top_left)
+    }
+
+    /// The columns and rows the pointer wraps around within.
+    fn bounds(&self) -> (Range<usize>, Range<usize>) {
+        match self.region {
+            Some((top_left, bottom_right)) => (
+                top_left.x..bottom_right.x + 1,
+                top_left.y..bottom_right.y + 1,
+            ),
+            None => {
+                let (width, height) = self.codebox.extent();
+                (0..width, 0..height)
+            }
+        }
+    }
+
+    /// Finds every op reachable from `start` heading in `dir`, following all
+    /// moves and mirrors. This is an approximation: both outcomes of `?` and
+    /// every direction of `x` are followed, while `.` jumps (whose targets
+    /// depend on the stack) and self-modification with `p` are ignored.
+    pub fn reachable_ops_from(&self, start: Pos, dir: Direction) -> HashSet<Pos> {
+        let mut reachable = HashSet::new();
+        let (width, height) = self.codebox.extent();
+        if width == 0 || height == 0 {
+            return reachable;
+        }
+
+        let mut seen = HashSet::new();
+        let mut pending = vec![(start, dir, ParseMode::Normal)];
+        while let Some((pos, dir, mode)) = pending.pop() {
+            if !seen.insert((pos, dir, mode)) {
+                continue;
+            }
+
+            let instr = match self.codebox.get_instruction(&pos) {
+                Instruction::Op(instr) => instr,
+                Instruction::Noop => {
+                    pending.extend(self.advance(pos, dir, mode).map(|pos| (pos, dir, mode)));
+                    continue;
+                }
+            };
+            reachable.insert(pos);
+
+            if let ParseMode::Text(quote_type) = mode {
+                if instr != quote_type {
+                    pending.extend(self.advance(pos, dir, mode).map(|pos| (pos, dir, mode)));
+                    continue;
+                }
+            }
+
+            match instr {
+                ';' | '.' => (),
+                '"' | '\'' => {
+                    let mode = match mode {
+                        ParseMode::Normal => ParseMode::Text(instr),
+                        ParseMode::Text(_) => ParseMode::Normal,
+                    };
+                    pending.extend(self.advance(pos, dir, mode).map(|pos| (pos, dir, mode)));
+                }
+                '!' | '?' => {
+                    let next = self.advance(pos, dir, mode);
+                    if instr == '?' {
+                        pending.extend(next.map(|pos| (pos, dir, mode)));
+                    }
+                    let skipped = next.and_then(|pos| self.advance(pos, dir, mode));
+                    pending.extend(skipped.map(|pos| (pos, dir, mode)));
+                }
+                'x' => {
+                    for &dir in &[
+                        Direction::North,
+                        Direction::East,
+                        Direction::South,
+                        Direction::West,
+                    ] {
+                        pending.extend(self.advance(pos, dir, mode).map(|pos| (pos, dir, mode)));
+                    }
+                }
+                _ => {
+                    let dir = dir.turn(instr);
+                    pending.extend(self.advance(pos, dir, mode).map(|pos| (pos, dir, mode)));
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Whether any `;` is reachable from the start of the program, as far as
+    /// `reachable_ops_from` can tell.
+    pub fn reachable_terminator(&self) -> bool {
+        !self.reachable_terminators().is_empty()
+    }
+
+    /// The position of every `;` reachable from the start of the program, as
+    /// far as `reachable_ops_from` can tell, in row-major order.
+    pub fn reachable_terminators(&self) -> Vec<Pos> {
+        let mut terminators: Vec<_> = self
+            .reachable_ops_from(self.start_pos(), Direction::East)
+            .into_iter()
+            .filter(|pos| self.codebox.get_instruction(pos) == Instruction::Op(';'))
+            .collect();
+        terminators.sort();
+        terminators
+    }
+
+    /// Where the pointer would move to from `pos`, as `move_to_next` does, or
+    /// `None` if it would never find another op.
+    fn advance(&self, pos: Pos, dir: Direction, mode: ParseMode) -> Option<Pos> {
+        let pos = self.get_next_pos(pos, dir)?;
+        if mode == ParseMode::Normal && self.codebox.get_instruction(&pos) == Instruction::Noop {
+            self.find_next_op(pos, dir)
+        } else {
+            Some(pos)
+        }
+    }
+
+    fn push_num(&mut self, chr: char) {
+        self.stack.top().push(chr.to_digit(16).unwrap() as f64);
+    }
+
+    fn push_char(&mut self, chr: char) {
+        self.stack.top().push((chr as u32) as f64);
+    }
+
+    fn switch_parse_mode(&mut self, quote_type: char) {
+        if self.mode == ParseMode::Normal {
+            self.mode = ParseMode::Text(quote_type);
+            self.text_start = Some(self.ptr);
+        } else {
+            self.mode = ParseMode::Normal;
+            self.text_start = None;
+        }
+    }
+
+    fn arithmetic(&mut self, op: char) -> Result<(), RuntimeError> {
+        let stack = self.stack.top();
+        // kept so they can be put back if the result is rejected
+        let operands: Vec<_> = if self.strict_numbers {
+            let len = stack.len();
+            stack.iter().skip(len.saturating_sub(2)).copied().collect()
+        } else {
+            vec![]
+        };
+        match op {
+            '+' => stack.add()?,
+            '-' => stack.subtract()?,
+            '*' => stack.multiply()?,
+            ',' => stack.divide()?,
+            '%' => stack.modulo()?,
+            _ => unreachable!("{} is not an arithmetic op", op),
+        }
+        let result = stack.pop()?;
+        if self.strict_numbers && !result.is_finite() {
+            stack.extend(operands);
+            return Err(StackError::NonFinite(result).into());
+        }
+        // -0 == 0, so this replaces both zeroes with +0
+        let canonical = self.canonicalize_zero && result == 0f64;
+        stack.push(if canonical { 0f64 } else { result });
+        Ok(())
+    }
+
+    fn load_pos(&mut self) -> Result<Pos, RuntimeError> {
+        let y = self.stack.top().pop()?;
+        let x = self.stack.top().pop()?;
+        // NaN fails every comparison, and anything too large for an isize
+        // (infinity included) would be clamped by the conversion, or break
+        // the pointer's movement, which is worked out in isizes
+        let valid =
+            |coord: f64| coord >= 0f64 && coord == coord.trunc() && coord < isize::MAX as f64;
+        if !valid(x) || !valid(y) {
+            Err(RuntimeError::InvalidPosition(x, y))?
+        } else {
+            Ok(Pos {
+                x: x as usize,
+                y: y as usize,
+            })
+        }
+    }
+
+    fn next_input(&mut self) -> Option<char> {
+        if let Some(chr) = self.pending_input.pop_front() {
+            return Some(chr);
+        }
+        let chr = self
+            .input_stream
+            .next()
+            .or_else(|| self.fed_input.pop_front());
+        if let (Some(consumed_input), Some(chr)) = (&mut self.consumed_input, chr) {
+            consumed_input.push(chr);
+        }
+        if let (Some(step_input), Some(chr)) = (&mut self.step_input, chr) {
+            step_input.push(chr);
+        }
+        chr
+    }
+
+    // whether there's input left to read, reading a character ahead if needed
+    fn peek_input(&mut self) -> bool {
+        if self.pending_input.is_empty() {
+            if let Some(chr) = self.next_input() {
+                self.pending_input.push_back(chr);
+            }
+        }
+        !self.pending_input.is_empty()
+    }
+
+    fn read_number(&mut self) {
+        let mut chr = self.next_input();
+        while matches!(chr, Some(chr) if chr.is_whitespace()) {
+            chr = self.next_input();
+        }
+
+        let mut num = None;
+        while let Some(digit) = chr.and_then(|chr| chr.to_digit(10)) {
+            num = Some(num.unwrap_or(0f64) * 10f64 + digit as f64);
+            chr = self.next_input();
+        }
+        if let Some(chr) = chr {
+            self.pending_input.push_front(chr);
+        }
+        match num {
+            Some(num) => self.stack.top().push(num),
+            None => self.reached_eof(),
+        }
+    }
+
+    fn reached_eof(&mut self) {
+        match self.eof_behavior {
+            EofBehavior::PushNegativeOne => self.stack.top().push(-1f64),
+            EofBehavior::PushValue(val) => self.stack.top().push(val),
+            EofBehavior::Halt => self.state = State::Done,
+        }
+    }
+
+    fn jump_relative(&mut self) -> Result<(), RuntimeError> {
+        let dy = self.stack.top().pop()?;
+        let dx = self.stack.top().pop()?;
+        if !dx.is_finite() || !dy.is_finite() || dx != dx.trunc() || dy != dy.trunc() {
+            return Err(RuntimeError::InvalidPosition(dx, dy));
+        }
+        let (cols, rows) = self.bounds();
+        self.ptr = Pos {
+            x: offset_wrapped(self.ptr.x, dx, cols),
+            y: offset_wrapped(self.ptr.y, dy, rows),
+        };
+        Ok(())
+    }
+
+    fn print_num(&mut self, num: f64) -> Result<(), RuntimeError> {
+        let separator = match &self.number_separator {
+            Some(separator) if self.last_output_was_number => separator.as_str(),
+            _ => "",
+        };
+        let (nan, infinity) = &self.non_finite_names;
+        let num = if self.strict_numbers && !num.is_finite() {
+            return Err(RuntimeError::NonFiniteOutput(num));
+        } else if let Some(formatter) = &self.number_formatter {
+            formatter(num)
+        } else if num.is_finite() {
+            match self.output_rounding {
+                Some(epsilon) if (num - num.round()).abs() <= epsilon => num.round().to_string(),
+                _ => num.to_string(),
+            }
+        } else if num.is_nan() {
+            nan.clone()
+        } else if num > 0f64 {
+            infinity.clone()
+        } else {
+            format!("-{}", infinity)
+        };
+        self.write_output(format!("{}{}", separator, num))?;
+        self.last_output_was_number = true;
+        Ok(())
+    }
+
+    fn print_char(&mut self, chr: f64) -> Result<(), RuntimeError> {
+        let chr = f64_to_char(chr)?;
+        let mut buf = [0; 4];
+        self.write_output(chr.encode_utf8(&mut buf).to_string())?;
+        self.last_output_was_number = false;
+        Ok(())
+    }
+
+    fn emit(&mut self, event: Event) {
+        if let Some(sink) = &mut self.event_sink {
+            sink(event);
+        }
+    }
+
+    fn emit_termination(&mut self) {
+        if self.warn_stack_imbalance && self.stack.total_len() > 0 {
+            let leftover = self.stack.flatten();
+            self.emit(Event::StackImbalance(leftover));
+        }
+        self.emit(Event::Terminated);
+    }
+
+    fn write_output(&mut self, s: String) -> Result<(), RuntimeError> {
+        let output_len = self.output_len + s.len();
+        if let Some(max_output) = self.max_output {
+            if output_len > max_output {
+                return Err(RuntimeError::OutputLimitExceeded);
+            }
+        }
+        self.output_len = output_len;
+        if let Some(recent_output) = &mut self.recent_output {
+            recent_output.push_str(&s);
+        }
+        if self.event_sink.is_some() {
+            self.emit(Event::Output(s.clone()));
+        }
+        match &mut self.output_buffer {
+            Some((buffer, capacity)) => {
+                buffer.push_str(&s);
+                if buffer.len() >= *capacity {
+                    self.flush_output()?;
+                }
+                Ok(())
+            }
+            None => self.send_output(&s),
+        }
+    }
+
+    /// Passes on any output held back by the output buffer.
+    pub fn flush_output(&mut self) -> Result<(), RuntimeError> {
+        match &mut self.output_buffer {
+            Some((buffer, _)) if !buffer.is_empty() => {
+                let s = std::mem::take(buffer);
+                self.send_output(&s)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn send_output(&mut self, s: &str) -> Result<(), RuntimeError> {
+        self.output
+            .write_all(s.as_bytes())
+            .and_then(|_| self.output.flush())
+            .map_err(|err| RuntimeError::OutputError(err.kind()))
+    }
+}
+
+fn get_wrapped_coord(coord: usize, incr: isize, bounds: Range<usize>) -> usize {
+    let coord = coord as isize;
+    if coord <= bounds.start as isize && incr < 0 {
+        bounds.end - 1
+    } else if coord + incr >= bounds.end as isize {
+        bounds.start
+    } else {
+        (coord + incr) as usize
+    }
+}
+
+/// Moves `coord` by one in the direction of `incr`, or returns `None` if that
+/// would wrap around `bounds` and `wrap` is false.
+fn step_coord(coord: usize, incr: isize, bounds: Range<usize>, wrap: bool) -> Option<usize> {
+    let at_edge = if incr < 0 {
+        coord <= bounds.start
+    } else {
+        coord + 1 >= bounds.end
+    };
+    if at_edge && !wrap {
+        None
+    } else {
+        Some(get_wrapped_coord(coord, incr, bounds))
+    }
+}
+
+/// Moves `coord` by `offset`, wrapping around within `bounds`. `coord` may lie
+/// outside `bounds`, after a `.` jump out of the region.
+fn offset_wrapped(coord: usize, offset: f64, bounds: Range<usize>) -> usize {
+    let len = (bounds.end - bounds.start) as f64;
+    let moved = coord as f64 - bounds.start as f64 + offset;
+    bounds.start + moved.rem_euclid(len) as usize
+}
+
+fn f64_to_char(chr: f64) -> Result<char, RuntimeError> {
+    if chr < u32::min_value() as f64 || chr > u32::max_value() as f64 || chr != chr.trunc() {
+        return Err(RuntimeError::CharConversionFailure(chr, Pos { x: 0, y: 0 }));
+    }
+    std::char::from_u32(chr as u32)
+        .ok_or(RuntimeError::CharConversionFailure(chr, Pos { x: 0, y: 0 }))
+}
+
+impl StackDelta {
+    fn between(before: &[f64], after: &[f64]) -> Self {
+        let common = before
+            .iter()
+            .zip(after)
+            .take_while(|(a, b)| a.to_bits() == b.to_bits())
+            .count();
+        Self {
+            pushed: after[common..].to_vec(),
+            popped: before[common..].to_vec(),
+        }
+    }
+}
+
+impl OpCategory {
+    pub const ALL: [OpCategory; 9] = [
+        OpCategory::Literal,
+        OpCategory::Arithmetic,
+        OpCategory::Comparison,
+        OpCategory::StackManipulation,
+        OpCategory::Trampoline,
+        OpCategory::Movement,
+        OpCategory::InputOutput,
+        OpCategory::CodeboxManipulation,
+        OpCategory::Other,
+    ];
+
+    /// The category `instr` belongs to when executed outside a string.
+    pub fn of(instr: char) -> OpCategory {
+        OPS.iter()
+            .find(|(_, ops)| ops.contains(instr))
+            .map_or(OpCategory::Other, |&(category, _)| category)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OpCategory::Literal => "literal",
+            OpCategory::Arithmetic => "arithmetic",
+            OpCategory::Comparison => "comparison",
+            OpCategory::StackManipulation => "stack manipulation",
+            OpCategory::Trampoline => "trampoline",
+            OpCategory::Movement => "movement",
+            OpCategory::InputOutput => "input/output",
+            OpCategory::CodeboxManipulation => "codebox manipulation",
+            OpCategory::Other => "other",
+        }
+    }
+}
+
+impl ExecutionStats {
+    /// The number of ops executed in `category`.
+    pub fn count(&self, category: OpCategory) -> usize {
+        self.ops_by_category[category as usize]
+    }
+}
+
+impl Direction {
+    /// The direction after passing through `instr`, which is unchanged unless
+    /// `instr` is an arrow or a mirror.
+    fn turn(&self, instr: char) -> Direction {
+        match instr {
+            '^' => Direction::North,
+            '>' => Direction::East,
+            'v' => Direction::South,
+            '<' => Direction::West,
+            '/' => match self {
+                Direction::North => Direction::East,
+                Direction::East => Direction::North,
+                Direction::South => Direction::West,
+                Direction::West => Direction::South,
+            },
+            '\\' => match self {
+                Direction::North => Direction::West,
+                Direction::East => Direction::South,
+                Direction::South => Direction::East,
+                Direction::West => Direction::North,
+            },
+            '|' if *self == Direction::West || *self == Direction::East => self.reverse(),
+            '_' if *self == Direction::North || *self == Direction::South => self.reverse(),
+            '#' => self.reverse(),
+            _ => *self,
+        }
+    }
+
+    pub fn reverse(&self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        }
+    }
+}
+
+impl Distribution<Direction> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Direction {
+        match rng.gen_range(0, 4) {
+            0 => Direction::North,
+            1 => Direction::East,
+            2 => Direction::South,
+            _ => Direction::West,
+        }
+    }
+}
+
+impl<T: Iterator<Item = char>> std::fmt::Debug for Interpreter<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Interpreter")
+            .field("codebox", &self.codebox)
+            .field("stack", &self.stack)
+            .field("ptr", &self.ptr)
+            .field("dir", &self.dir)
+            .field("state", &self.state)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+impl RuntimeError {
+    /// The process exit code used to report this error:
+    ///
+    /// | code | error                                            |
+    /// |------|--------------------------------------------------|
+    /// | 2    | `InvalidInstruction`, `UnimplementedInstruction` |
+    /// | 3    | stack underflow                                  |
+    /// | 4    | division by zero                                 |
+    /// | 5    | `InvalidPosition`                                |
+    /// | 6    | `CharConversionFailure`                          |
+    /// | 7    | `UnexpectedEOF`                                  |
+    /// | 8    | `OutputLimitExceeded`                            |
+    /// | 9    | `NonFiniteOutput`                                |
+    /// | 10   | `NonFiniteStackValue`                            |
+    /// | 11   | `UnterminatedString`                             |
+    /// | 12   | `OutOfBounds`                                    |
+    /// | 13   | `OutputError`                                    |
+    /// | 14   | `StepLimitExceeded`                              |
+    /// | 15   | `PokeLimitExceeded`                              |
+    /// | 16   | non-finite arithmetic result                     |
+    /// | 17   | `JumpLimitExceeded`                              |
+    /// | 18   | `NoInstructionsReachable`                        |
+    ///
+    /// Code 1 is left for failures outside the interpreter, such as bad
+    /// arguments or IO errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RuntimeError::InvalidInstruction(_, _)
+            | RuntimeError::UnimplementedInstruction(_, _) => 2,
+            RuntimeError::StackError(StackError::Underflow, _) => 3,
+            RuntimeError::StackError(StackError::DivisionByZero, _) => 4,
+            RuntimeError::StackError(StackError::NonFinite(_), _) => 16,
+            RuntimeError::InvalidPosition(_, _) => 5,
+            RuntimeError::CharConversionFailure(_, _) => 6,
+            RuntimeError::UnexpectedEOF => 7,
+            RuntimeError::OutputLimitExceeded => 8,
+            RuntimeError::NonFiniteOutput(_) => 9,
+            RuntimeError::NonFiniteStackValue(_) => 10,
+            RuntimeError::UnterminatedString(_) => 11,
+            RuntimeError::OutOfBounds(_, _) => 12,
+            RuntimeError::OutputError(_) => 13,
+            RuntimeError::StepLimitExceeded => 14,
+            RuntimeError::PokeLimitExceeded => 15,
+            RuntimeError::JumpLimitExceeded => 17,
+            RuntimeError::NoInstructionsReachable(_, _) => 18,
+        }
+    }
+
+    /// Where the pointer was when the error occurred, for errors raised by a
+    /// failing instruction.
+    pub fn pos(&self) -> Option<Pos> {
+        match *self {
+            RuntimeError::InvalidInstruction(_, pos)
+            | RuntimeError::UnimplementedInstruction(_, pos)
+            | RuntimeError::CharConversionFailure(_, pos)
+            | RuntimeError::StackError(_, pos) => Some(pos),
+            _ => None,
+        }
+    }
+
+    // errors are raised deep inside instructions, which don't know where the
+    // pointer is, so they start out at the origin and are moved here once the
+    // instruction has failed
+    fn at(self, pos: Pos) -> Self {
+        match self {
+            RuntimeError::InvalidInstruction(instr, _) => {
+                RuntimeError::InvalidInstruction(instr, pos)
+            }
+            RuntimeError::UnimplementedInstruction(instr, _) => {
+                RuntimeError::UnimplementedInstruction(instr, pos)
+            }
+            RuntimeError::CharConversionFailure(val, _) => {
+                RuntimeError::CharConversionFailure(val, pos)
+            }
+            RuntimeError::StackError(err, _) => RuntimeError::StackError(err, pos),
+            err => err,
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            RuntimeError::InvalidInstruction(instr, Pos { x, y }) => {
+                write!(f, "invalid instruction '{}' at ({}, {})", instr, x, y)
+            }
+            RuntimeError::UnimplementedInstruction(instr, Pos { x, y }) => {
+                write!(f, "unimplemented instruction '{}' at ({}, {})", instr, x, y)
+            }
+            RuntimeError::InvalidPosition(x, y) => write!(f, "invalid position ({}, {})", x, y),
+            RuntimeError::CharConversionFailure(val, Pos { x, y }) => write!(
+                f,
+                "character conversion failed for value {} at ({}, {})",
+                val, x, y
+            ),
+            RuntimeError::StackError(err, Pos { x, y }) => write!(f, "{} at ({}, {})", err, x, y),
+            RuntimeError::UnexpectedEOF => write!(f, "unexpected end of input"),
+            RuntimeError::OutputLimitExceeded => write!(f, "output limit exceeded"),
+            RuntimeError::NonFiniteOutput(val) => write!(f, "cannot output {}", val),
+            RuntimeError::NonFiniteStackValue(val) => {
+                write!(f, "cannot put {} on the stack", val)
+            }
+            RuntimeError::UnterminatedString(Pos { x, y }) => {
+                write!(f, "string starting at ({}, {}) is never closed", x, y)
+            }
+            RuntimeError::OutOfBounds(Pos { x, y }, dir) => write!(
+                f,
+                "pointer left the codebox at ({}, {}) heading {}",
+                x,
+                y,
+                format!("{:?}", dir).to_lowercase()
+            ),
+            RuntimeError::OutputError(kind) => write!(f, "failed to write output: {:?}", kind),
+            RuntimeError::StepLimitExceeded => write!(f, "step limit exceeded"),
+            RuntimeError::PokeLimitExceeded => write!(f, "limit on p writes exceeded"),
+            RuntimeError::JumpLimitExceeded => write!(f, "limit on . jumps exceeded"),
+            RuntimeError::NoInstructionsReachable(Pos { x, y }, dir) => write!(
+                f,
+                "no instructions reachable from ({}, {}) heading {}",
+                x,
+                y,
+                format!("{:?}", dir).to_lowercase()
+            ),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+impl From<StackError> for RuntimeError {
+    fn from(error: StackError) -> Self {
+        RuntimeError::StackError(error, Pos { x: 0, y: 0 })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{
+        Direction, EofBehavior, Event, ExtensionMode, OpCategory, ParseMode, StackDelta, State,
+    };
+    use super::{Interpreter, OpHandler, RuntimeError};
+    use crate::codebox::{Codebox, Instruction, Pos, OPS};
+    use crate::stack::{parse_stack_value, StackError};
+    use rand::Rng;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::io::{self, Write};
+    use std::iter::empty;
+    use std::rc::Rc;
+
+    /// Collects everything written to it into a string that can still be
+    /// read once the interpreter owns the writer.
+    pub(crate) struct SharedOutput(pub(crate) Rc<RefCell<String>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().push_str(&String::from_utf8_lossy(buf));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capture_output<T: Iterator<Item = char>>(
+        interpreter: Interpreter<T>,
+    ) -> (Interpreter<T>, Rc<RefCell<String>>) {
+        let output = Rc::new(RefCell::new(String::new()));
+        let interpreter = interpreter.with_output(SharedOutput(Rc::clone(&output)));
+        (interpreter, output)
+    }
+
+    #[test]
+    fn test_initial_stack() {
+        let values = ["65", "'B'"]
+            .iter()
+            .map(|s| parse_stack_value(s).unwrap())
+            .collect();
+        let mut interpreter = Interpreter::new("", empty()).with_initial_stack(values);
+
+        assert_eq!(interpreter.stack.top().pop(), Ok(66f64));
+        assert_eq!(interpreter.stack.top().pop(), Ok(65f64));
+    }
+
+    #[test]
+    fn test_initial_stack_program() {
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("+n;", empty()).with_initial_stack(vec![3f64, 4f64]));
+        assert_eq!(interpreter.stack.flatten(), interpreter.stack_snapshot());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "7");
+    }
+
+    #[test]
+    fn test_initial_stack_rejects_non_finite() {
+        match Interpreter::new("", empty()).try_with_initial_stack(vec![1f64, f64::NAN]) {
+            Err(RuntimeError::NonFiniteStackValue(val)) => assert!(val.is_nan()),
+            res => panic!("expected NonFiniteStackValue, got {:?}", res.map(|_| ())),
+        }
+
+        let mut interpreter = Interpreter::new("", empty())
+            .try_with_initial_stack(vec![1f64, 2f64])
+            .unwrap();
+        assert_eq!(interpreter.stack.top().pop(), Ok(2f64));
+    }
+
+    #[test]
+    fn test_strict_strings() {
+        let mut interpreter = Interpreter::new(" \"ab", empty()).with_strict_strings();
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::UnterminatedString(Pos { x: 1, y: 0 }))
+        );
+
+        // without the check, the quote closes its own string on wrapping
+        let mut interpreter = Interpreter::new(" \"ab", empty());
+        for _ in 0..6 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.mode, ParseMode::Normal);
+        assert_eq!(interpreter.stack.flatten(), vec![97f64, 98f64, 32f64]);
+
+        let mut interpreter = Interpreter::new("\"ab\";", empty()).with_strict_strings();
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+    }
+
+    #[test]
+    fn test_display_precision() {
+        let values = vec![1f64, 2.5, 3.33333];
+        let interpreter = Interpreter::new("", empty()).with_initial_stack(values.clone());
+        assert_eq!(interpreter.render_stack(), "[1, 2.5, 3.33333]");
+
+        let interpreter = Interpreter::new("", empty())
+            .with_initial_stack(values)
+            .with_display_precision(2);
+        assert_eq!(interpreter.render_stack(), "[1, 2.5, 3.33]");
+    }
+
+    #[test]
+    fn test_step_after_end() {
+        let (mut interpreter, output) = capture_output(Interpreter::new("12n;3n", empty()));
+        for _ in 0..4 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.state, State::Done);
+        let ptr = interpreter.ptr;
+
+        for _ in 0..3 {
+            assert_eq!(interpreter.step(), Ok(None));
+        }
+        assert_eq!(interpreter.stack_snapshot(), vec![1f64]);
+        assert_eq!(interpreter.ptr, ptr);
+        assert_eq!(*output.borrow(), "2");
+        assert_eq!(interpreter.stats().steps, 4);
+    }
+
+    #[test]
+    fn test_try_step() {
+        let mut interpreter = Interpreter::new("12+1+", empty());
+        interpreter.try_step().unwrap();
+        interpreter.try_step().unwrap();
+        let info = interpreter.try_step().unwrap();
+        assert_eq!(info.pos, Pos { x: 2, y: 0 });
+        assert_eq!(info.instr, Instruction::Op('+'));
+        assert_eq!(info.delta.popped, vec![1f64, 2f64]);
+        assert_eq!(info.delta.pushed, vec![3f64]);
+
+        let mut interpreter = Interpreter::new("1+", empty()).with_history();
+        interpreter.try_step().unwrap();
+        let before = interpreter.snapshot();
+        assert_eq!(
+            interpreter.try_step(),
+            Err(RuntimeError::StackError(
+                StackError::Underflow,
+                Pos { x: 1, y: 0 }
+            ))
+        );
+        assert!(interpreter.snapshot() == before);
+        assert_eq!(interpreter.ptr, Pos { x: 1, y: 0 });
+        assert_eq!(interpreter.history().unwrap().len(), 1);
+
+        // a rejected step doesn't count towards the step limit, coverage or
+        // cycle detection
+        let mut interpreter = Interpreter::new("1+", empty())
+            .with_coverage()
+            .with_halt_on_cycle();
+        interpreter.set_step_limit(Some(2));
+        interpreter.try_step().unwrap();
+        for _ in 0..3 {
+            assert!(matches!(
+                interpreter.try_step(),
+                Err(RuntimeError::StackError(StackError::Underflow, _))
+            ));
+        }
+        assert_eq!(interpreter.stats().steps, 1);
+        assert_eq!(interpreter.coverage().unwrap().len(), 1);
+        assert_eq!(interpreter.seen_states.as_ref().unwrap().len(), 1);
+        interpreter.stack.top().push(1f64);
+        interpreter.try_step().unwrap();
+        assert_eq!(interpreter.stats().steps, 2);
+
+        // a cell written by `p` is put back when the move after it fails
+        let mut interpreter = Interpreter::new("f7*00p", empty())
+            .with_wrap(false, false)
+            .with_max_pokes(1);
+        for _ in 0..5 {
+            interpreter.try_step().unwrap();
+        }
+        let before = interpreter.snapshot();
+        assert!(matches!(
+            interpreter.try_step(),
+            Err(RuntimeError::OutOfBounds(_, Direction::East))
+        ));
+        assert!(interpreter.snapshot() == before);
+        assert_eq!(interpreter.pokes, 0);
+
+        // so is input read by a failing step
+        let mut interpreter = Interpreter::new("I", "12x".chars())
+            .with_number_input('I')
+            .with_input_recording()
+            .with_wrap(false, false);
+        assert!(interpreter.try_step().is_err());
+        assert!(interpreter.stack.top().is_empty());
+        interpreter.wrap = (true, true);
+        interpreter.try_step().unwrap();
+        assert_eq!(interpreter.stack.top().pop(), Ok(12f64));
+        assert_eq!(interpreter.next_input(), Some('x'));
+        assert_eq!(interpreter.consumed_input(), &['1', '2', 'x']);
+
+        // and the random number generator
+        let mut interpreter = Interpreter::new("x", empty())
+            .with_wrap(false, false)
+            .with_seed(1);
+        let mut rng = interpreter.rng.clone();
+        assert!(interpreter.try_step().is_err());
+        assert!(!interpreter.used_random);
+        assert_eq!(interpreter.rng.gen::<u64>(), rng.gen::<u64>());
+    }
+
+    #[test]
+    fn test_relative_jump() {
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("30j;;;1n;", empty()).with_relative_jump('j'));
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "1");
+
+        // jumping backwards past the edge wraps around
+        let mut interpreter = Interpreter::new("0a-0j  ", empty()).with_relative_jump('j');
+        for _ in 0..5 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.ptr, Pos { x: 2, y: 0 });
+
+        let mut interpreter = Interpreter::new("30j", empty());
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(
+            interpreter.step(),
+            Err(RuntimeError::InvalidInstruction('j', Pos { x: 2, y: 0 }))
+        );
+    }
+
+    #[test]
+    fn test_op_categories() {
+        for &(category, ops) in OPS.iter() {
+            for instr in ops.chars() {
+                assert_eq!(OpCategory::of(instr), category);
+                assert!(OpCategory::ALL.contains(&category));
+
+                // every listed op is implemented
+                let mut interpreter = Interpreter::new(&instr.to_string(), empty())
+                    .with_initial_stack(vec![0f64; 4])
+                    .with_output(io::sink());
+                let res = interpreter.step();
+                assert!(
+                    !matches!(res, Err(RuntimeError::InvalidInstruction(_, _))),
+                    "{} is not implemented",
+                    instr
+                );
+            }
+        }
+        assert_eq!(OpCategory::of('j'), OpCategory::Other);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut interpreter = Interpreter::new("\"ab\"+1n;", empty()).with_output(io::sink());
+        interpreter.run_to_end().unwrap();
+
+        let stats = interpreter.stats();
+        assert_eq!(stats.steps, 8);
+        assert_eq!(stats.count(OpCategory::Literal), 3);
+        assert_eq!(stats.count(OpCategory::InputOutput), 3);
+        assert_eq!(stats.count(OpCategory::Arithmetic), 1);
+        assert_eq!(stats.count(OpCategory::Other), 1);
+        assert!(!stats.self_modified);
+
+        let mut interpreter = Interpreter::new("'a'00p;", empty());
+        interpreter.run_to_end().unwrap();
+        assert!(interpreter.stats().self_modified);
+    }
+
+    #[test]
+    fn test_peak_stack_depth() {
+        let mut interpreter =
+            Interpreter::new("123[45+]~~~~;", empty()).with_initial_stack(vec![0f64]);
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.peak_stack_depth(), 5);
+        assert_eq!(interpreter.stack.total_len(), 0);
+    }
+
+    #[test]
+    fn test_wrap() {
+        // wrapping horizontally but not vertically
+        let mut interpreter = Interpreter::new("1>", empty()).with_wrap(true, false);
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.ptr, Pos { x: 0, y: 0 });
+
+        let mut interpreter = Interpreter::new("v\n1", empty()).with_wrap(true, false);
+        interpreter.step().unwrap();
+        assert_eq!(
+            interpreter.step(),
+            Err(RuntimeError::OutOfBounds(
+                Pos { x: 0, y: 1 },
+                Direction::South
+            ))
+        );
+
+        // ops beyond an edge that doesn't wrap aren't found by skipping noops
+        let mut interpreter = Interpreter::new("!;>   ", empty()).with_wrap(false, true);
+        interpreter.step().unwrap();
+        assert_eq!(
+            interpreter.step(),
+            Err(RuntimeError::OutOfBounds(
+                Pos { x: 5, y: 0 },
+                Direction::East
+            ))
+        );
+
+        let mut interpreter = Interpreter::new("!;>   ", empty());
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.ptr, Pos { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn test_blocking_input() {
+        let (interpreter, output) = capture_output(Interpreter::new("io", empty()));
+        let mut interpreter = interpreter.with_blocking_input();
+
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+        assert_eq!(interpreter.state, State::AwaitingInput);
+        assert_eq!(interpreter.ptr, Pos { x: 0, y: 0 });
+        assert_eq!(interpreter.stack.total_len(), 0);
+
+        interpreter.feed_input("ab");
+        assert_eq!(interpreter.state, State::Running);
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+        assert_eq!(interpreter.state, State::AwaitingInput);
+        assert_eq!(*output.borrow(), "ab");
+
+        // with the input closed, i pushes -1, which o can't print
+        interpreter.close_input();
+        assert_eq!(interpreter.state, State::Running);
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::CharConversionFailure(
+                -1f64,
+                Pos { x: 1, y: 0 }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_number_input() {
+        let mut interpreter = Interpreter::new("Iii;", " 42x".chars()).with_number_input('I');
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack.flatten(), vec![42f64, 120f64, -1f64]);
+
+        let mut interpreter = Interpreter::new("I;", "  ".chars()).with_number_input('I');
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack.flatten(), vec![-1f64]);
+    }
+
+    #[test]
+    fn test_output_buffer_flushed_on_error() {
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("'hi'oo+", empty()).with_output_buffer(64));
+        for _ in 0..5 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(*output.borrow(), "");
+
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::StackError(
+                StackError::Underflow,
+                Pos { x: 6, y: 0 }
+            ))
+        );
+        assert_eq!(*output.borrow(), "ih");
+    }
+
+    #[test]
+    fn test_run_fragment() {
+        let (mut interpreter, output) = capture_output(Interpreter::new("", empty()));
+        interpreter.run_fragment("34+").unwrap();
+        assert_eq!(*output.borrow(), "");
+        interpreter.run_fragment("n;").unwrap();
+        assert_eq!(*output.borrow(), "7");
+
+        // running off the edge ends the fragment normally, not as an error
+        let events = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&events);
+        let terminations = Rc::new(RefCell::new(vec![]));
+        let hook_terminations = Rc::clone(&terminations);
+        let mut interpreter = Interpreter::new("", empty())
+            .with_break_on_error()
+            .with_event_sink(Box::new(move |event| sink.borrow_mut().push(event)))
+            .with_on_terminate(Box::new(move |interpreter| {
+                hook_terminations
+                    .borrow_mut()
+                    .push(interpreter.state.clone())
+            }));
+        assert_eq!(interpreter.run_fragment("12"), Ok(()));
+        assert_eq!(interpreter.state, State::Done);
+        assert_eq!(interpreter.ptr, Pos { x: 1, y: 0 });
+        assert_eq!(*terminations.borrow(), vec![State::Done]);
+        assert!(!events
+            .borrow()
+            .iter()
+            .any(|event| matches!(event, Event::Error(_))));
+        assert_eq!(events.borrow().last(), Some(&Event::Terminated));
+
+        // a normal program still reports the edge as an error afterwards
+        interpreter.load_program("1");
+        interpreter.wrap = (false, false);
+        assert!(matches!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::OutOfBounds(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_text_start_pos() {
+        let mut interpreter = Interpreter::new("1'ab';", empty());
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.text_start_pos(), None);
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.text_start_pos(), Some(Pos { x: 1, y: 0 }));
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.text_start_pos(), Some(Pos { x: 1, y: 0 }));
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.text_start_pos(), None);
+    }
+
+    #[test]
+    fn test_trampolines_in_text() {
+        let mut interpreter = Interpreter::new("\"!a?b\";", empty()).with_initial_stack(vec![0f64]);
+        interpreter.run_to_end().unwrap();
+        assert_eq!(
+            interpreter.stack_snapshot(),
+            vec![0f64, 33f64, 97f64, 63f64, 98f64]
+        );
+
+        let mut interpreter = Interpreter::new("'?!';", empty());
+        for _ in 0..3 {
+            interpreter.step().unwrap();
+            assert_eq!(interpreter.mode, ParseMode::Text('\''));
+        }
+        assert_eq!(interpreter.ptr, Pos { x: 3, y: 0 });
+        assert_eq!(interpreter.stack_snapshot(), vec![63f64, 33f64]);
+    }
+
+    #[test]
+    fn test_consumed_input() {
+        let code = "ii+n;";
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new(code, "abc".chars()).with_input_recording());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.consumed_input(), &['a', 'b']);
+
+        let replay: String = interpreter.consumed_input().iter().collect();
+        let (mut replayed, replay_output) = capture_output(Interpreter::new(code, replay.chars()));
+        replayed.run_to_end().unwrap();
+        assert_eq!(*replay_output.borrow(), *output.borrow());
+        assert_eq!(*output.borrow(), "195");
+    }
+
+    #[test]
+    fn test_put_ahead_of_pointer() {
+        // the blank cell just ahead becomes a `;`, which is executed next
+        let (mut interpreter, output) = capture_output(Interpreter::new("';'60p 1n;", empty()));
+        for _ in 0..6 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.ptr, Pos { x: 6, y: 0 });
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.state, State::Done);
+        assert_eq!(*output.borrow(), "");
+    }
+
+    #[test]
+    fn test_put_behind_pointer() {
+        // overwriting a cell already passed only matters once the pointer
+        // comes back around
+        let (mut interpreter, output) = capture_output(Interpreter::new("'n'00p1n;", empty()));
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "1");
+        assert_eq!(
+            interpreter.codebox.get_instruction(&Pos { x: 0, y: 0 }),
+            Instruction::Op('n')
+        );
+    }
+
+    #[test]
+    fn test_coverage_bounds() {
+        let mut interpreter =
+            Interpreter::new("\"hello, world\"rv\n          o;!?l<\n     ;", empty())
+                .with_output(io::sink())
+                .with_coverage();
+        assert_eq!(interpreter.coverage_bounds(), None);
+
+        interpreter.run_to_end().unwrap();
+        let coverage = interpreter.coverage().unwrap();
+        assert!(coverage.contains(&Pos { x: 15, y: 0 }));
+        assert!(coverage.contains(&Pos { x: 11, y: 1 }));
+        assert!(!coverage.contains(&Pos { x: 5, y: 2 }));
+        assert_eq!(
+            interpreter.coverage_bounds(),
+            Some((Pos { x: 0, y: 0 }, Pos { x: 15, y: 1 }))
+        );
+
+        let interpreter = Interpreter::new(";", empty());
+        assert_eq!(interpreter.coverage_bounds(), None);
+    }
+
+    #[test]
+    fn test_output_multi_byte_char() {
+        let (mut interpreter, output) = capture_output(
+            Interpreter::new("o;", empty()).with_initial_stack(vec![0x1F41F as f64]),
+        );
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+        assert_eq!(output.borrow().as_bytes(), [0xF0, 0x9F, 0x90, 0x9F]);
+        assert_eq!(interpreter.output_len(), 4);
+
+        let (mut interpreter, output) = capture_output(Interpreter::new("io;", "🐟".chars()));
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+        assert_eq!(*output.borrow(), "🐟");
+    }
+
+    #[test]
+    fn test_output_to_writer() {
+        struct BrokenPipe;
+
+        impl Write for BrokenPipe {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut interpreter = Interpreter::new("1n;", empty()).with_output(BrokenPipe);
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::OutputError(io::ErrorKind::BrokenPipe))
+        );
+
+        let mut interpreter = Interpreter::new("'ab'oo;", empty()).with_output(Vec::new());
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+        assert_eq!(interpreter.output_len(), 2);
+    }
+
+    #[test]
+    fn test_step_n() {
+        let mut interpreter = Interpreter::new("12345+++;", empty());
+        assert_eq!(interpreter.step_n(5), Ok(5));
+        assert_eq!(interpreter.stack.total_len(), 5);
+        assert_eq!(interpreter.step_n(5), Ok(4));
+        assert_eq!(interpreter.state, State::Done);
+        assert_eq!(interpreter.step_n(5), Ok(0));
+    }
+
+    #[test]
+    fn test_put_far_away() {
+        let mut interpreter = Interpreter::new("'x'a:*:*:*:p;", empty());
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+        let far = Pos {
+            x: 100_000_000,
+            y: 100_000_000,
+        };
+        assert_eq!(
+            interpreter.codebox.get_instruction(&far),
+            Instruction::Op('x')
+        );
+    }
+
+    #[test]
+    fn test_put_invalid_position() {
+        for &coord in &[f64::INFINITY, 1e300, isize::MAX as f64] {
+            let mut interpreter =
+                Interpreter::new("p;", empty()).with_initial_stack(vec![120f64, coord, 0f64]);
+            assert_eq!(
+                interpreter.run_to_end(),
+                Err(RuntimeError::InvalidPosition(coord, 0f64))
+            );
+            let mut interpreter =
+                Interpreter::new("g;", empty()).with_initial_stack(vec![0f64, coord]);
+            assert_eq!(
+                interpreter.run_to_end(),
+                Err(RuntimeError::InvalidPosition(0f64, coord))
+            );
+        }
+
+        // the largest coordinate below `isize::MAX` that a float can hold
+        let coord = isize::MAX as f64 - 1024f64;
+        let mut interpreter = Interpreter::new("g;", empty()).with_initial_stack(vec![0f64, coord]);
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+    }
+
+    #[test]
+    fn test_max_pokes() {
+        // writes a 1 below the program forever
+        let mut interpreter = Interpreter::new("'1'01p", empty()).with_max_pokes(10);
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::PokeLimitExceeded)
+        );
+        assert_eq!(interpreter.pokes, 10);
+        assert_eq!(interpreter.stack.total_len(), 3);
+
+        let mut interpreter = Interpreter::new("'1'01p;", empty()).with_max_pokes(1);
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+    }
+
+    #[test]
+    fn test_max_jumps() {
+        // jumps back to the start forever
+        let mut interpreter = Interpreter::new("20.", empty()).with_max_jumps(5);
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::JumpLimitExceeded)
+        );
+        assert_eq!(interpreter.jumps, 5);
+        assert_eq!(interpreter.stack.total_len(), 2);
+
+        let mut interpreter = Interpreter::new("30.;;", empty()).with_max_jumps(1);
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let mut interpreter = Interpreter::new(">", empty());
+        interpreter.set_step_limit(Some(100));
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::StepLimitExceeded)
+        );
+        assert_eq!(interpreter.stats().steps, 100);
+
+        let mut interpreter = Interpreter::new("12+;", empty());
+        interpreter.set_step_limit(Some(4));
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+    }
+
+    #[test]
+    fn test_output_rounding() {
+        let values = vec![2.9999999999, 2.5];
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("n' 'on;", empty()).with_initial_stack(values.clone()));
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "2.5 2.9999999999");
+
+        let (mut interpreter, output) = capture_output(
+            Interpreter::new("n' 'on;", empty())
+                .with_initial_stack(values)
+                .with_output_rounding(1e-6),
+        );
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "2.5 3");
+    }
+
+    #[test]
+    fn test_run_to_breakpoint() {
+        let breakpoints: HashSet<_> = vec![Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 }]
+            .into_iter()
+            .collect();
+        let mut interpreter = Interpreter::new("12+n;", empty()).with_output(io::sink());
+
+        assert_eq!(interpreter.run_to_breakpoint(&breakpoints), Ok(true));
+        assert_eq!(interpreter.ptr, Pos { x: 2, y: 0 });
+        assert_eq!(interpreter.run_to_breakpoint(&breakpoints), Ok(false));
+        assert_eq!(interpreter.state, State::Done);
+    }
+
+    #[test]
+    fn test_len_of_substack() {
+        let mut interpreter = Interpreter::new("1232[45l;", empty());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(
+            interpreter.stack_snapshot(),
+            vec![2f64, 3f64, 4f64, 5f64, 4f64]
+        );
+    }
+
+    #[test]
+    fn test_stack_snapshot() {
+        let mut interpreter = Interpreter::new("1232[45;", empty());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack_snapshot(), vec![2f64, 3f64, 4f64, 5f64]);
+        assert_eq!(
+            interpreter.stack.flatten(),
+            vec![1f64, 2f64, 3f64, 4f64, 5f64]
+        );
+    }
+
+    #[test]
+    fn test_next_instruction() {
+        let mut interpreter = Interpreter::new("1  2\n>  3:;", empty());
+        assert_eq!(interpreter.next_instruction(), Some('2'));
+
+        interpreter.set_position(Pos { x: 0, y: 1 }).unwrap();
+        assert_eq!(interpreter.next_instruction(), Some('3'));
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.next_instruction(), Some(':'));
+        let expected = interpreter.next_instruction();
+        assert_eq!(interpreter.step(), Ok(Some('3')));
+        assert_eq!(interpreter.step().unwrap(), expected);
+
+        let interpreter = Interpreter::new("\"a ", empty());
+        assert_eq!(interpreter.next_instruction(), Some('a'));
+    }
+
+    #[test]
+    fn test_set_position() {
+        let mut interpreter = Interpreter::new("1n;\n2n;", empty()).with_output(io::sink());
+        assert_eq!(interpreter.position(), Pos { x: 0, y: 0 });
+        assert_eq!(interpreter.direction(), Direction::East);
+
+        interpreter.set_position(Pos { x: 0, y: 1 }).unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.stack_snapshot(), vec![2f64]);
+        assert_eq!(interpreter.position(), Pos { x: 1, y: 1 });
+
+        interpreter.set_direction(Direction::West);
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.position(), Pos { x: 0, y: 1 });
+        assert_eq!(interpreter.direction(), Direction::West);
+
+        match interpreter.set_position(Pos { x: 3, y: 0 }) {
+            Err(RuntimeError::InvalidPosition(x, y)) => assert_eq!((x, y), (3f64, 0f64)),
+            other => panic!("expected InvalidPosition, got {:?}", other),
+        }
+        assert_eq!(interpreter.position(), Pos { x: 0, y: 1 });
+    }
+
+    #[test]
+    fn test_max_output() {
+        let mut interpreter = Interpreter::new(">1n", empty())
+            .with_output(io::sink())
+            .with_max_output(5);
+
+        match interpreter.run_to_end() {
+            Err(RuntimeError::OutputLimitExceeded) => (),
+            res => panic!("expected OutputLimitExceeded, got {:?}", res),
+        }
+        assert_eq!(interpreter.output_len, 5);
+    }
+
+    #[test]
+    fn test_output_len() {
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("\"olleh\"ooooo12+n;", empty()));
+
+        assert_eq!(interpreter.output_len(), 0);
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "hello3");
+        assert_eq!(interpreter.output_len(), output.borrow().len());
+    }
+
+    #[test]
+    fn test_eof_value() {
+        let mut interpreter = Interpreter::new("i;", empty());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack.top().pop(), Ok(-1f64));
+
+        let mut interpreter = Interpreter::new("i;", empty()).with_eof_value(0f64);
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack.top().pop(), Ok(0f64));
+    }
+
+    #[test]
+    fn test_eof_behavior() {
+        let run = |behavior| {
+            let mut interpreter = Interpreter::new("1i2;", empty());
+            interpreter.set_eof_behavior(behavior);
+            interpreter.run_to_end().unwrap();
+            (interpreter.stack_snapshot(), interpreter.state)
+        };
+        assert_eq!(
+            run(EofBehavior::PushNegativeOne),
+            (vec![1f64, -1f64, 2f64], State::Done)
+        );
+        assert_eq!(
+            run(EofBehavior::PushValue(0f64)),
+            (vec![1f64, 0f64, 2f64], State::Done)
+        );
+        assert_eq!(run(EofBehavior::Halt), (vec![1f64], State::Done));
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut interpreter = Interpreter::new("1 n;", empty())
+            .with_output(io::sink())
+            .with_history();
+        interpreter.run_to_end().unwrap();
+
+        let frames = interpreter.frames();
+        assert_eq!(frames.len(), interpreter.history().unwrap().len());
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1], "1 \x1b[7mn\x1b[0m;");
+    }
+
+    #[test]
+    fn test_from_grid() {
+        let grid = vec![vec!['1', '2', '+', 'v'], vec![';', ' ', 'n', '<']];
+        let mut interpreter = Interpreter::from_grid(grid, empty()).with_output(io::sink());
+        assert_eq!(interpreter.codebox.width(), 4);
+        assert_eq!(interpreter.codebox.height(), 2);
+        assert!(interpreter.run_to_end().is_ok());
+        assert_eq!(interpreter.output_len, 1);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let mut interpreter = Interpreter::new("+;", empty());
+        let err = interpreter.run_to_end().unwrap_err();
+        assert_eq!(err.exit_code(), 3);
+
+        let mut interpreter = Interpreter::new("Z;", empty());
+        let err = interpreter.run_to_end().unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_epsilon() {
+        let values = vec![1e10f64, 1e10f64 + 1f64];
+        let mut interpreter = Interpreter::new("=;", empty()).with_initial_stack(values.clone());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack.top().pop(), Ok(0f64));
+
+        let mut interpreter = Interpreter::new("=;", empty())
+            .with_initial_stack(values)
+            .with_epsilon(1e-9);
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack.top().pop(), Ok(1f64));
+    }
+
+    #[test]
+    fn test_from_codebox() {
+        let mut codebox = Codebox::from_grid(vec![vec!['1', '2', '+', ' ', ';']]);
+        codebox.set_instruction(Pos { x: 3, y: 0 }, 'n');
+        let mut interpreter = Interpreter::from_codebox(codebox, empty()).with_output(io::sink());
+
+        assert!(interpreter.run_to_end().is_ok());
+        assert_eq!(interpreter.output_len, 1);
+    }
+
+    #[test]
+    fn test_reachable_terminator() {
+        let interpreter = Interpreter::new(">  v\n^  <\n ;", empty());
+        assert!(!interpreter.reachable_terminator());
+
+        let interpreter = Interpreter::new(">?;v\n^  <", empty());
+        assert!(interpreter.reachable_terminator());
+
+        let interpreter = Interpreter::new("\"hello, world\"rv\n          o;!?l<", empty());
+        assert!(interpreter.reachable_terminator());
+    }
+
+    #[test]
+    fn test_reachable_terminators() {
+        let interpreter = Interpreter::new(">  v\n;  <\n ;", empty());
+        assert_eq!(
+            interpreter.reachable_terminators(),
+            vec![Pos { x: 0, y: 1 }]
+        );
+
+        let interpreter = Interpreter::new(">?;v\n;  <", empty());
+        assert_eq!(
+            interpreter.reachable_terminators(),
+            vec![Pos { x: 2, y: 0 }, Pos { x: 0, y: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_number_separator() {
+        let (mut interpreter, output) = capture_output(Interpreter::new("12nn'a'o3n;", empty()));
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "21a3");
+
+        let (mut interpreter, output) = capture_output(
+            Interpreter::new("12nn'a'o3n;", empty()).with_number_separator(Some(" ".to_string())),
+        );
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "2 1a3");
+    }
+
+    #[test]
+    fn test_number_formatter() {
+        let (mut interpreter, output) = capture_output(
+            Interpreter::new("5n;", empty())
+                .with_number_formatter(Box::new(|num| format!("#{}", num))),
+        );
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "#5");
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        for &op in &[',', '%'] {
+            let mut interpreter = Interpreter::new(&format!("10{};", op), empty());
+            assert_eq!(
+                interpreter.run_to_end(),
+                Err(RuntimeError::StackError(
+                    StackError::DivisionByZero,
+                    Pos { x: 2, y: 0 }
+                ))
+            );
+            assert_eq!(interpreter.stack_snapshot(), vec![1f64, 0f64]);
+        }
+    }
+
+    #[test]
+    fn test_strict_arithmetic() {
+        let mut interpreter =
+            Interpreter::new("-;", empty()).with_initial_stack(vec![f64::INFINITY, f64::INFINITY]);
+        interpreter.run_to_end().unwrap();
+        assert!(interpreter.stack_snapshot()[0].is_nan());
+
+        let mut interpreter =
+            Interpreter::new("-;", empty()).with_initial_stack(vec![f64::INFINITY, f64::INFINITY]);
+        interpreter.set_strict_numbers(true);
+        match interpreter.run_to_end() {
+            Err(RuntimeError::StackError(StackError::NonFinite(val), _)) if val.is_nan() => (),
+            res => panic!("expected a NaN result to be rejected, got {:?}", res),
+        }
+        assert_eq!(
+            interpreter.stack_snapshot(),
+            vec![f64::INFINITY, f64::INFINITY]
+        );
+
+        let mut interpreter = Interpreter::new("a*;", empty()).with_initial_stack(vec![1e308]);
+        interpreter.set_strict_numbers(true);
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::StackError(
+                StackError::NonFinite(f64::INFINITY),
+                Pos { x: 1, y: 0 }
+            ))
+        );
+        assert_eq!(interpreter.stack_snapshot(), vec![1e308, 10f64]);
+    }
+
+    #[test]
+    fn test_extension_ops() {
+        let push_ten = || -> OpHandler {
+            Box::new(|stack| {
+                stack.top().push(10f64);
+                Ok(())
+            })
+        };
+
+        let mut interpreter = Interpreter::new("12+Y;", empty())
+            .with_op('+', push_ten())
+            .with_op('Y', push_ten());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack_snapshot(), vec![3f64, 10f64]);
+
+        let mut interpreter = Interpreter::new("12+Y;", empty())
+            .with_op('+', push_ten())
+            .with_op('Y', push_ten())
+            .with_extension_mode(ExtensionMode::ExtensionFirst);
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack_snapshot(), vec![1f64, 2f64, 10f64, 10f64]);
+    }
+
+    #[test]
+    fn test_discard_empty_stack() {
+        let mut interpreter = Interpreter::new("~1n;", empty());
+
+        match interpreter.run_to_end() {
+            Err(RuntimeError::StackError(StackError::Underflow, _)) => (),
+            res => panic!("expected stack underflow, got {:?}", res),
+        }
+        assert_eq!(interpreter.state, State::Running);
+        assert_eq!(interpreter.output_len, 0);
+    }
+
+    #[test]
+    fn test_verbose_trace() {
+        let trace = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&trace);
+        let mut interpreter =
+            Interpreter::new("12+;", empty()).with_verbose_trace(move |instr, pos, delta| {
+                sink.borrow_mut().push((instr, pos, delta.clone()))
+            });
+        interpreter.run_to_end().unwrap();
+
+        let trace = trace.borrow();
+        assert_eq!(trace.len(), 4);
+        assert_eq!(
+            trace[2],
+            (
+                '+',
+                Pos { x: 2, y: 0 },
+                StackDelta {
+                    pushed: vec![3f64],
+                    popped: vec![1f64, 2f64],
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_break_on_error() {
+        let mut interpreter = Interpreter::new("1~~;", empty()).with_break_on_error();
+        let err = RuntimeError::StackError(StackError::Underflow, Pos { x: 2, y: 0 });
+
+        assert_eq!(interpreter.run_to_end(), Err(err.clone()));
+        assert_eq!(interpreter.state(), &State::Errored(err.clone()));
+        assert_eq!(interpreter.ptr, Pos { x: 2, y: 0 });
+        assert_eq!(interpreter.stack().iter_current().count(), 0);
+
+        assert_eq!(interpreter.step(), Err(err));
+        assert_eq!(interpreter.ptr, Pos { x: 2, y: 0 });
+    }
+
+    #[test]
+    fn test_snapshot_cycle() {
+        let mut interpreter = Interpreter::new("1~", empty());
+        let mut seen = HashSet::new();
+        let mut steps = 0;
+        while seen.insert(interpreter.snapshot()) {
+            interpreter.step().unwrap();
+            steps += 1;
+        }
+        assert_eq!(steps, 2);
+
+        let mut interpreter = Interpreter::new("1+", empty()).with_initial_stack(vec![0f64]);
+        let mut seen = HashSet::new();
+        for _ in 0..10 {
+            assert!(seen.insert(interpreter.snapshot()));
+            interpreter.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_no_instructions_reachable() {
+        let mut interpreter = Interpreter::new("\n", empty());
+        assert_eq!(
+            interpreter.step(),
+            Err(RuntimeError::NoInstructionsReachable(
+                Pos { x: 0, y: 0 },
+                Direction::East
+            ))
+        );
+
+        // jumping onto a blank row
+        let mut interpreter = Interpreter::new("02.\n\n ", empty());
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(
+            interpreter.step(),
+            Err(RuntimeError::NoInstructionsReachable(
+                Pos { x: 0, y: 2 },
+                Direction::East
+            ))
+        );
+        assert_eq!(interpreter.step().map_err(|err| err.exit_code()), Err(18));
+
+        // the v itself is found again going around the column
+        let mut interpreter = Interpreter::new("v  \n   ", empty());
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.ptr, Pos { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn test_new_strict() {
+        let interpreter = Interpreter::new_strict("1j2k;", empty());
+        assert_eq!(
+            interpreter.err(),
+            Some(vec![(Pos { x: 1, y: 0 }, 'j'), (Pos { x: 3, y: 0 }, 'k')])
+        );
+
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new_strict("\"hi\"oo;", empty()).unwrap());
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+        assert_eq!(*output.borrow(), "ih");
+    }
+
+    #[test]
+    fn test_approx_memory_bytes() {
+        let mut interpreter = Interpreter::new("1;", empty());
+        let before = interpreter.approx_memory_bytes();
+
+        interpreter.stack.top().extend(vec![1f64; 1000]);
+        let pushed = interpreter.approx_memory_bytes();
+        assert!(pushed >= before + 1000 * 8);
+
+        interpreter
+            .codebox
+            .set_instruction(Pos { x: 50, y: 50 }, '1');
+        assert!(interpreter.approx_memory_bytes() > pushed);
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut interpreter = Interpreter::new("1+:&", empty()).with_initial_stack(vec![0f64]);
+        for _ in 0..4 {
+            interpreter.step().unwrap();
+        }
+        let snapshot = interpreter.snapshot();
+        let (stack, ptr) = (interpreter.stack.clone(), interpreter.ptr);
+        for _ in 0..9 {
+            interpreter.step().unwrap();
+        }
+        assert_ne!(interpreter.stack, stack);
+
+        interpreter.restore(snapshot.clone());
+        assert_eq!(interpreter.stack, stack);
+        assert_eq!(interpreter.ptr, ptr);
+        assert_eq!(interpreter.state, State::Running);
+        assert!(interpreter.snapshot() == snapshot);
+
+        let mut interpreter = Interpreter::new(";", empty());
+        let snapshot = interpreter.snapshot();
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.state, State::Done);
+        interpreter.restore(snapshot);
+        assert_eq!(interpreter.state, State::Running);
+    }
+
+    #[test]
+    fn test_run_until_output() {
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("\"ydaer\"ooooov\n            >^", empty()));
+
+        let res = interpreter.run_until_output(|output| output.contains("ready"));
+        assert_eq!(res, Ok(true));
+        assert_eq!(*output.borrow(), "ready");
+        assert_eq!(interpreter.ptr, Pos { x: 12, y: 0 });
+        assert_eq!(interpreter.state, State::Running);
+
+        let mut interpreter = Interpreter::new("1n;", empty()).with_output(io::sink());
+        let res = interpreter.run_until_output(|output| output.contains("ready"));
+        assert_eq!(res, Ok(false));
+        assert_eq!(interpreter.state, State::Done);
+    }
+
+    #[test]
+    fn test_blank_cell_value() {
+        let mut interpreter = Interpreter::new("01g;", empty());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack.top().pop(), Ok(0f64));
+
+        let mut interpreter = Interpreter::new("01g;", empty()).with_blank_cell_value(-1f64);
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stack.top().pop(), Ok(-1f64));
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            RuntimeError::StackError(StackError::Underflow, Pos { x: 1, y: 0 }).to_string(),
+            "stack underflow at (1, 0)"
+        );
+        assert_eq!(
+            RuntimeError::InvalidInstruction('~', Pos { x: 4, y: 2 }).to_string(),
+            "invalid instruction '~' at (4, 2)"
+        );
+        assert_eq!(
+            RuntimeError::CharConversionFailure(1.5, Pos { x: 0, y: 0 }).to_string(),
+            "character conversion failed for value 1.5 at (0, 0)"
+        );
+        assert_eq!(
+            RuntimeError::OutOfBounds(Pos { x: 0, y: 2 }, Direction::South).to_string(),
+            "pointer left the codebox at (0, 2) heading south"
+        );
+    }
+
+    #[test]
+    fn test_error_position() {
+        let mut interpreter = Interpreter::new("1v\n Z\n ;", empty());
+        let err = interpreter.run_to_end().unwrap_err();
+        assert_eq!(
+            err,
+            RuntimeError::InvalidInstruction('Z', Pos { x: 1, y: 1 })
+        );
+        assert_eq!(err.pos(), Some(Pos { x: 1, y: 1 }));
+
+        let mut interpreter = Interpreter::new("12,o", empty());
+        let err = interpreter.run_to_end().unwrap_err();
+        assert_eq!(
+            err,
+            RuntimeError::CharConversionFailure(0.5, Pos { x: 3, y: 0 })
+        );
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let mut interpreter = Interpreter::new("12Z;", empty());
+        let err = interpreter.run_to_end().unwrap_err();
+        let diagnosis = interpreter.diagnose(&err);
+
+        assert!(diagnosis.starts_with("invalid instruction 'Z' at (2, 0) while executing"));
+        assert!(diagnosis.contains("'Z'"));
+        assert!(diagnosis.contains("(2, 0)"));
+        assert!(diagnosis.contains("[1, 2]"));
+    }
+
+    #[test]
+    fn test_region() {
+        let code = "ZZZZZ\nZ12 Z\nZ   Z\nZ   Z\nZZZZZ";
+        let mut interpreter =
+            Interpreter::new(code, empty()).with_region(Pos { x: 1, y: 1 }, Pos { x: 3, y: 3 });
+        assert_eq!(interpreter.ptr, Pos { x: 1, y: 1 });
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.ptr, Pos { x: 1, y: 1 });
+
+        let code = "ZZZZZ\nZv  Z\nZ   Z\nZ1  Z\nZZZZZ";
+        let mut interpreter =
+            Interpreter::new(code, empty()).with_region(Pos { x: 1, y: 1 }, Pos { x: 3, y: 3 });
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.ptr, Pos { x: 1, y: 1 });
+        assert_eq!(interpreter.stack.top().pop(), Ok(1f64));
+
+        // corners given the other way round make the same region
+        let code = "ZZZZZ\nZ12 Z\nZ   Z\nZ   Z\nZZZZZ";
+        let mut interpreter =
+            Interpreter::new(code, empty()).with_region(Pos { x: 3, y: 3 }, Pos { x: 1, y: 1 });
+        assert_eq!(
+            interpreter.region,
+            Some((Pos { x: 1, y: 1 }, Pos { x: 3, y: 3 }))
+        );
+        assert_eq!(interpreter.ptr, Pos { x: 1, y: 1 });
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.ptr, Pos { x: 1, y: 1 });
+        assert_eq!(interpreter.stack_snapshot(), vec![1f64, 2f64]);
+
+        // a relative jump from outside the region, after `.` left it, lands
+        // back inside
+        let mut interpreter = Interpreter::new("Zj  1000.;", empty())
+            .with_region(Pos { x: 4, y: 0 }, Pos { x: 9, y: 0 })
+            .with_relative_jump('j');
+        for _ in 0..5 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.ptr, Pos { x: 1, y: 0 });
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.ptr, Pos { x: 9, y: 0 });
+    }
+
+    #[test]
+    fn test_flattened_stack() {
+        let mut interpreter = Interpreter::new("1232[41[5;", empty());
+        interpreter.run_to_end().unwrap();
+
+        assert_eq!(interpreter.stack().depth(), 3);
+        assert_eq!(
+            interpreter.stack().flatten(),
+            vec![1f64, 2f64, 3f64, 4f64, 5f64]
+        );
+    }
+
+    #[test]
+    fn test_non_finite_output() {
+        let values = vec![f64::NAN, f64::NEG_INFINITY];
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("nn;", empty()).with_initial_stack(values.clone()));
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "-infNaN");
+
+        let (mut interpreter, output) = capture_output(
+            Interpreter::new("nn;", empty())
+                .with_initial_stack(values.clone())
+                .with_non_finite_names("nan", "Infinity"),
+        );
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "-Infinitynan");
+
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("nn;", empty()).with_initial_stack(values));
+        interpreter.set_strict_numbers(true);
+        match interpreter.run_to_end() {
+            Err(RuntimeError::NonFiniteOutput(num)) => assert_eq!(num, f64::NEG_INFINITY),
+            res => panic!("expected NonFiniteOutput, got {:?}", res),
+        }
+        assert_eq!(*output.borrow(), "");
+    }
+
+    #[test]
+    fn test_named_programs() {
+        let (mut interpreter, output) = capture_output(Interpreter::new("", empty()));
+        interpreter.load_named("first", "51n;");
+        interpreter.load_named("second", "ln;");
+
+        assert!(interpreter.activate("first"));
+        interpreter.run_to_end().unwrap();
+        assert!(interpreter.activate("second"));
+        interpreter.run_to_end().unwrap();
+        assert!(interpreter.activate("first"));
+        interpreter.run_to_end().unwrap();
+        assert!(!interpreter.activate("third"));
+
+        assert_eq!(*output.borrow(), "101");
+    }
+
+    fn assert_turns(instr: char, cases: &[(Direction, Direction)]) {
+        for &(from, to) in cases {
+            let mut interpreter = Interpreter::new(&instr.to_string(), empty());
+            interpreter.dir = from;
+            interpreter.execute_instruction(instr).unwrap();
+            assert_eq!(interpreter.dir, to, "{:?} through '{}'", from, instr);
+        }
+    }
+
+    #[test]
+    fn test_mirrors() {
+        use Direction::*;
+
+        assert_turns(
+            '/',
+            &[(North, East), (East, North), (South, West), (West, South)],
+        );
+        assert_turns(
+            '\\',
+            &[(North, West), (East, South), (South, East), (West, North)],
+        );
+        assert_turns(
+            '_',
+            &[(North, South), (East, East), (South, North), (West, West)],
+        );
+    }
+
+    #[test]
+    fn test_halt_on_cycle() {
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("1:n~", empty()).with_halt_on_cycle());
+        assert_eq!(interpreter.run_to_end(), Ok(()));
+        assert_eq!(interpreter.state, State::Done);
+        assert_eq!(*output.borrow(), "1");
+
+        // after an x, the same state may be followed by a different direction
+        let mut interpreter = Interpreter::new("x", empty())
+            .with_halt_on_cycle()
+            .with_seed(0);
+        interpreter.set_step_limit(Some(20));
+        assert_eq!(
+            interpreter.run_to_end(),
+            Err(RuntimeError::StepLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn test_event_sink() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&events);
+        let mut interpreter = Interpreter::new("1n;", empty())
+            .with_output(io::sink())
+            .with_event_sink(Box::new(move |event| sink.borrow_mut().push(event)));
+        interpreter.run_to_end().unwrap();
+
+        let step = |x, instr| Event::Step {
+            pos: Pos { x, y: 0 },
+            instr: Instruction::Op(instr),
+        };
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                step(0, '1'),
+                step(1, 'n'),
+                Event::Output("1".to_string()),
+                step(2, ';'),
+                Event::Terminated,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stack_imbalance_warning() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&events);
+        let mut interpreter = Interpreter::new("12n;", empty())
+            .with_output(io::sink())
+            .with_stack_imbalance_warning()
+            .with_event_sink(Box::new(move |event| sink.borrow_mut().push(event)));
+        interpreter.run_to_end().unwrap();
+
+        let leftover: Vec<_> = events
+            .borrow()
+            .iter()
+            .filter_map(|event| match event {
+                Event::StackImbalance(values) => Some(values.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(leftover, vec![vec![1f64]]);
+        assert_eq!(events.borrow().last(), Some(&Event::Terminated));
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&events);
+        let mut interpreter = Interpreter::new("1n;", empty())
+            .with_output(io::sink())
+            .with_stack_imbalance_warning()
+            .with_event_sink(Box::new(move |event| sink.borrow_mut().push(event)));
+        interpreter.run_to_end().unwrap();
+        assert!(!events
+            .borrow()
+            .iter()
+            .any(|event| matches!(event, Event::StackImbalance(_))));
+    }
+
+    #[test]
+    fn test_helloworld() {
+        let mut interpreter = Interpreter::new(
+            "\"hello, world\"rv
+          o;!?l<",
+            empty(),
+        );
+
+        assert_eq!(interpreter.run_to_string(), Ok("hello, world".to_string()));
+    }
+
+    #[test]
+    fn test_step_returns_op() {
+        let mut interpreter = Interpreter::new(
+            "\"hello, world\"rv
+          o;!?l<",
+            empty(),
+        )
+        .with_output(io::sink());
+
+        let mut ops = vec![];
+        while interpreter.state() != &State::Done {
+            ops.push(interpreter.step().unwrap());
+        }
+
+        // the space inside the string is a blank cell, so no op is executed
+        let mut expected: Vec<_> = "\"hello,".chars().map(Some).collect();
+        expected.push(None);
+        expected.extend("world\"rv<".chars().map(Some));
+        for _ in 0.."hello, world".len() {
+            expected.extend("l?!o<".chars().map(Some));
+        }
+        expected.extend("l?;".chars().map(Some));
+        assert_eq!(ops, expected);
+    }
+
+    #[test]
+    fn test_trace_hook() {
+        let trace = Rc::new(RefCell::new(vec![]));
+        let hook_trace = Rc::clone(&trace);
+        let mut interpreter = Interpreter::new("1 v\n  ;", empty());
+        interpreter.set_trace_hook(Box::new(move |pos, instr, &dir| {
+            hook_trace.borrow_mut().push((pos, instr, dir))
+        }));
+        interpreter.run_to_end().unwrap();
+        assert_eq!(
+            *trace.borrow(),
+            vec![
+                (Pos { x: 0, y: 0 }, '1', Direction::East),
+                (Pos { x: 2, y: 0 }, 'v', Direction::East),
+                (Pos { x: 2, y: 1 }, ';', Direction::South),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_on_terminate() {
+        let calls = Rc::new(RefCell::new(vec![]));
+        let hook_calls = Rc::clone(&calls);
+        let mut interpreter = Interpreter::new("12+n;", empty())
+            .with_output(io::sink())
+            .with_on_terminate(Box::new(move |interpreter| {
+                hook_calls
+                    .borrow_mut()
+                    .push((interpreter.stats().steps, interpreter.output_len()))
+            }));
+        interpreter.run_to_end().unwrap();
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*calls.borrow(), vec![(5, 1)]);
+
+        // an error suspended by break-on-error is reported again by every
+        // call, but the hook still only fires for the first
+        let calls = Rc::new(RefCell::new(0));
+        let hook_calls = Rc::clone(&calls);
+        let mut interpreter = Interpreter::new("1+", empty())
+            .with_output(io::sink())
+            .with_break_on_error()
+            .with_on_terminate(Box::new(move |_| *hook_calls.borrow_mut() += 1));
+        assert!(interpreter.run_to_end().is_err());
+        assert!(interpreter.run_to_end().is_err());
+        assert_eq!(*calls.borrow(), 1);
+
+        interpreter.load_program("1n;");
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*calls.borrow(), 2);
+    }
+
+    #[test]
+    fn test_canonical_zero() {
+        let (mut interpreter, output) = capture_output(Interpreter::new("01-0*n;", empty()));
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "-0");
+
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("01-0*:n;", empty()).with_canonical_zero());
+        interpreter.run_to_end().unwrap();
+        assert_eq!(*output.borrow(), "0");
+        assert!(interpreter.stack_snapshot()[0].is_sign_positive());
+    }
+
+    #[test]
+    fn test_seed() {
+        let run = |seed| {
+            let mut interpreter = Interpreter::new("xxx\nxxx\nxxx", empty())
+                .with_seed(seed)
+                .with_history();
+            interpreter.step_n(100).unwrap();
+            interpreter.history().unwrap().to_vec()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn test_coverage_listing() {
+        let mut interpreter = Interpreter::new(
+            "\"hello, world\"rv
+          o;!?l<
+          Z",
+            empty(),
+        )
+        .with_coverage();
+        interpreter.run_to_string().unwrap();
+
+        let listing = interpreter.coverage_listing();
+        let lines: Vec<_> = listing.lines().collect();
+        assert!(lines[0].starts_with("\x1b[1m\"\x1b[0m\x1b[1mh\x1b[0m"));
+        assert!(lines[1].contains("\x1b[1mo\x1b[0m\x1b[1m;\x1b[0m"));
+        assert_eq!(lines[2].trim(), "\x1b[2mZ\x1b[0m");
+    }
+
+    #[test]
+    fn test_fizzbuzz() {
+        let mut interpreter = Interpreter::new(
+            "0voa                            ~/?=0:\\
+ voa            oooo'Buzz'~<     /
+ >1+:aa*1+=?;::5%:{3%:@*?\\?/'zziF'oooo/
+ ^oa                 n:~~/",
+            empty(),
+        );
+
+        let expected: String = (1..=100)
+            .map(|i| match (i % 3, i % 5) {
+                (0, 0) => "FizzBuzz\n".to_string(),
+                (0, _) => "Fizz\n".to_string(),
+                (_, 0) => "Buzz\n".to_string(),
+                _ => format!("{}\n", i),
+            })
+            .collect();
+        assert_eq!(interpreter.run_to_string(), Ok(expected));
+    }
+
+    #[test]
+    fn test_run_to_string() {
+        let mut interpreter = Interpreter::new("'a'o1n'b'o;", empty());
+        assert_eq!(interpreter.run_to_string(), Ok("a1b".to_string()));
+
+        // output still in the buffer from earlier steps comes first
+        let (mut interpreter, output) =
+            capture_output(Interpreter::new("'a'o1n'b'o;", empty()).with_output_buffer(64));
+        interpreter.step_n(4).unwrap();
+        assert_eq!(interpreter.run_to_string(), Ok("a1b".to_string()));
+        assert_eq!(*output.borrow(), "");
+    }
+
+    #[test]
+    fn test_quine() {
+        let mut interpreter = Interpreter::new("\"r00gol?!;40.", empty()).with_output(io::sink());
+
+        let res = interpreter.run_to_end();
+        if res.is_err() {
+            println!();
+            println!("{:#?}", interpreter);
+        }
+        println!();
+    }
+
+    #[test]
+    fn test_quine2() {
+        let mut interpreter = Interpreter::new(
+            "0>:a$f8+$p1+:5-?vv     
+ ^              <>~0v  
+v             <     <  
+>0v          ;^?-6:+1~<
 v <                  < 
 >$:{:}$go$   1+:f9+-?^^",
             empty(),
-        );
+        )
+        .with_output(io::sink());
 
         let res = interpreter.run_to_end();
         if res.is_err() {
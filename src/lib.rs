@@ -1,10 +1,19 @@
 #![feature(backtrace)]
 
+mod check;
 mod codebox;
+mod input;
 mod interpreter;
 mod stack;
 
-pub use interpreter::Interpreter;
+pub use check::{check_output, OutputMismatch};
+pub use codebox::{is_valid_op, Codebox, Instruction, Pos, OPS};
+pub use input::FilterCharIter;
+pub use interpreter::{
+    Direction, EofBehavior, Event, ExecutionStats, ExtensionMode, Interpreter, InterpreterState,
+    OpCategory, RuntimeError, StackDelta, State, StepInfo,
+};
+pub use stack::{parse_stack_value, ParseError, ProgramStack, Stack, StackError};
 
 #[cfg(test)]
 mod tests {
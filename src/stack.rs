@@ -1,18 +1,23 @@
 use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ProgramStack {
     base: Stack,
     substacks: Vec<Stack>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StackError {
     Underflow,
-    DivideByZero, // does this belong here?
+    /// `,` or `%` with a divisor of zero. The operands are left on the stack.
+    DivisionByZero,
+    /// Arithmetic gave NaN or infinity while strict numbers are on. The
+    /// operands are left on the stack.
+    NonFinite(f64),
 }
 
 impl ProgramStack {
@@ -27,6 +32,67 @@ impl ProgramStack {
         self.substacks.last_mut().unwrap_or(&mut self.base)
     }
 
+    pub fn curr(&self) -> &Stack {
+        self.substacks.last().unwrap_or(&self.base)
+    }
+
+    /// The value at the bottom of the active stack.
+    pub fn bottom(&self) -> Result<f64, StackError> {
+        self.curr().bottom()
+    }
+
+    /// Removes and returns the value at the bottom of the active stack.
+    pub fn pop_front(&mut self) -> Result<f64, StackError> {
+        self.top().pop_front()
+    }
+
+    /// The value held in the active stack's register, if any.
+    pub fn register(&self) -> Option<f64> {
+        self.curr().register()
+    }
+
+    /// The number of stacks, counting the base stack and every substack.
+    pub fn depth(&self) -> usize {
+        self.substacks.len() + 1
+    }
+
+    /// The number of entries across the base stack and every substack.
+    pub fn total_len(&self) -> usize {
+        self.base.len() + self.substacks.iter().map(Stack::len).sum::<usize>()
+    }
+
+    /// Roughly how many bytes the entries of every stack take up, counting
+    /// space allocated but not yet used.
+    pub fn approx_memory_bytes(&self) -> usize {
+        let capacity = self.base.entries.capacity()
+            + self
+                .substacks
+                .iter()
+                .map(|stack| stack.entries.capacity())
+                .sum::<usize>();
+        capacity * std::mem::size_of::<f64>()
+    }
+
+    /// All entries across the base stack and every substack, base first.
+    pub fn flatten(&self) -> Vec<f64> {
+        std::iter::once(&self.base)
+            .chain(&self.substacks)
+            .flat_map(|stack| stack.iter().copied())
+            .collect()
+    }
+
+    /// Iterates over the active stack's entries, bottom first, without
+    /// consuming them.
+    pub fn iter_current(&self) -> impl Iterator<Item = &f64> {
+        self.curr().iter()
+    }
+
+    /// Drops every substack and empties the base stack, register included.
+    pub fn clear_all(&mut self) {
+        self.substacks.clear();
+        self.base.clear();
+    }
+
     // [
     pub fn split_stack(&mut self) -> Result<(), StackError> {
         let new_stack = self.top().split()?;
@@ -44,7 +110,7 @@ impl ProgramStack {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Stack {
     entries: VecDeque<f64>,
     register: Option<f64>,
@@ -66,91 +132,124 @@ impl Stack {
         self.entries.push_back(val);
     }
 
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn register(&self) -> Option<f64> {
+        self.register
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The value at the bottom of the stack, without removing it.
+    pub fn bottom(&self) -> Result<f64, StackError> {
+        self.entries.front().copied().ok_or(StackError::Underflow)
+    }
+
+    /// Removes and returns the value at the bottom of the stack.
+    pub fn pop_front(&mut self) -> Result<f64, StackError> {
+        self.entries.pop_front().ok_or(StackError::Underflow)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &f64> {
+        self.entries.iter()
+    }
+
     pub fn clear(&mut self) {
         self.register = None;
         self.entries.clear();
     }
 
+    /// Moves the top `n` values below `n` itself into a new stack. The count
+    /// is checked before anything is removed, so an underflow leaves the
+    /// stack as it was.
     pub fn split(&mut self) -> Result<Self, StackError> {
-        let n = self.pop()? as usize;
-        let self_len = self.entries.len();
+        let n = *self.entries.back().ok_or(StackError::Underflow)? as usize;
+        let self_len = self.entries.len() - 1;
         if self_len < n {
             Err(StackError::Underflow)
         } else {
+            self.entries.pop_back();
             let s = self.entries.split_off(self_len - n);
             Ok(s.into_iter().collect())
         }
     }
 
+    /// The top two values as `(y, x)`, where `x` is the topmost, without
+    /// removing them.
+    fn operands(&self) -> Result<(f64, f64), StackError> {
+        let len = self.entries.len();
+        if len < 2 {
+            return Err(StackError::Underflow);
+        }
+        Ok((self.entries[len - 2], self.entries[len - 1]))
+    }
+
+    /// Replaces the top two values with `op(y, x)`. The operands are checked
+    /// before anything is removed, so an underflow leaves the stack as it was.
+    fn apply_binary<F: FnOnce(f64, f64) -> f64>(&mut self, op: F) -> Result<(), StackError> {
+        let (y, x) = self.operands()?;
+        self.entries.truncate(self.entries.len() - 2);
+        self.push(op(y, x));
+        Ok(())
+    }
+
     // +
     pub fn add(&mut self) -> Result<(), StackError> {
-        let x = self.pop()?;
-        let y = self.pop()?;
-        self.push(y + x);
-        Ok(())
+        self.apply_binary(|y, x| y + x)
     }
 
     // -
     pub fn subtract(&mut self) -> Result<(), StackError> {
-        let x = self.pop()?;
-        let y = self.pop()?;
-        self.push(y - x);
-        Ok(())
+        self.apply_binary(|y, x| y - x)
     }
 
     // *
     pub fn multiply(&mut self) -> Result<(), StackError> {
-        let x = self.pop()?;
-        let y = self.pop()?;
-        self.push(y * x);
-        Ok(())
+        self.apply_binary(|y, x| y * x)
     }
 
     // ,
     pub fn divide(&mut self) -> Result<(), StackError> {
-        let x = self.pop()?;
-        if x == 0f64 {
-            return Err(StackError::DivideByZero);
-        }
-        let y = self.pop()?;
-        self.push(y / x);
-        Ok(())
+        self.check_divisor()?;
+        self.apply_binary(|y, x| y / x)
     }
 
     // %
     pub fn modulo(&mut self) -> Result<(), StackError> {
-        let x = self.pop()?;
-        let y = self.pop()?;
-        self.push(y % x);
+        self.check_divisor()?;
+        self.apply_binary(|y, x| y % x)
+    }
+
+    fn check_divisor(&self) -> Result<(), StackError> {
+        if self.operands()?.1 == 0f64 {
+            return Err(StackError::DivisionByZero);
+        }
         Ok(())
     }
 
     // =
     pub fn equals(&mut self) -> Result<(), StackError> {
-        let x = self.pop()?;
-        let y = self.pop()?;
-        self.push(if (y - x).abs() < std::f64::EPSILON {
-            1f64
-        } else {
-            0f64
-        });
-        Ok(())
+        self.equals_within(f64::EPSILON)
+    }
+
+    /// As `equals`, but treating values as equal when they differ by at most
+    /// `epsilon`, either absolutely or relative to the larger magnitude.
+    pub fn equals_within(&mut self, epsilon: f64) -> Result<(), StackError> {
+        self.apply_binary(|y, x| if approx_eq(y, x, epsilon) { 1f64 } else { 0f64 })
     }
 
     // )
     pub fn greater_than(&mut self) -> Result<(), StackError> {
-        let x = self.pop()?;
-        let y = self.pop()?;
-        self.push(if y > x { 1f64 } else { 0f64 });
-        Ok(())
+        self.apply_binary(|y, x| if y > x { 1f64 } else { 0f64 })
     }
 
     // (
     pub fn less_than(&mut self) -> Result<(), StackError> {
-        let x = self.pop()?;
-        let y = self.pop()?;
-        self.push(if y < x { 1f64 } else { 0f64 });
-        Ok(())
+        self.apply_binary(|y, x| if y < x { 1f64 } else { 0f64 })
     }
 
     // :
@@ -202,7 +301,7 @@ impl Stack {
 
     // &
     pub fn swap_register(&mut self) -> Result<(), StackError> {
-        if let Some(val) = self.register {
+        if let Some(val) = self.register.take() {
             self.push(val);
         } else {
             self.register = Some(self.pop()?);
@@ -211,6 +310,76 @@ impl Stack {
     }
 }
 
+fn approx_eq(x: f64, y: f64, epsilon: f64) -> bool {
+    let diff = (x - y).abs();
+    diff <= epsilon || diff <= epsilon * x.abs().max(y.abs())
+}
+
+/// Why a stack value couldn't be parsed by `parse_stack_value`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Empty,
+    /// Quotes around anything but a single character.
+    InvalidChar(String),
+    InvalidNumber(String),
+}
+
+/// Parses a single value for preloading the stack: either a number, such as
+/// `3`, `-2.5` or `1e3`, or a single-quoted character such as `'A'`, which is
+/// pushed as its code point.
+pub fn parse_stack_value(s: &str) -> Result<f64, ParseError> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    if s.len() > 1 && s.starts_with('\'') && s.ends_with('\'') {
+        let mut chars = s[1..s.len() - 1].chars();
+        return match (chars.next(), chars.next()) {
+            (Some(chr), None) => Ok((chr as u32) as f64),
+            _ => Err(ParseError::InvalidChar(s.to_string())),
+        };
+    }
+    s.parse()
+        .map_err(|_| ParseError::InvalidNumber(s.to_string()))
+}
+
+impl Default for ProgramStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// stacks are compared by the bit patterns of their values, so that they can be
+// hashed and so that a NaN is equal to itself
+impl PartialEq for Stack {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries.len() == other.entries.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|(a, b)| a.to_bits() == b.to_bits())
+            && self.register.map(f64::to_bits) == other.register.map(f64::to_bits)
+    }
+}
+
+impl Eq for Stack {}
+
+impl Hash for Stack {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.entries.len().hash(state);
+        for val in self.iter() {
+            val.to_bits().hash(state);
+        }
+        self.register.map(f64::to_bits).hash(state);
+    }
+}
+
 impl FromIterator<f64> for Stack {
     fn from_iter<I: IntoIterator<Item = f64>>(iter: I) -> Self {
         Self {
@@ -237,16 +406,30 @@ impl Extend<f64> for Stack {
 
 impl Display for StackError {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{:?}", self)
+        match self {
+            StackError::Underflow => write!(f, "stack underflow"),
+            StackError::DivisionByZero => write!(f, "division by zero"),
+            StackError::NonFinite(val) => write!(f, "arithmetic gave {}", val),
+        }
     }
 }
 
-impl Error for StackError {
-    fn description(&self) -> &str {
-        ""
+impl Error for StackError {}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            ParseError::Empty => write!(f, "empty stack value"),
+            ParseError::InvalidChar(s) => {
+                write!(f, "invalid stack value {}: expected a single character", s)
+            }
+            ParseError::InvalidNumber(s) => write!(f, "invalid stack value {}: not a number", s),
+        }
     }
 }
 
+impl Error for ParseError {}
+
 #[cfg(test)]
 mod test {
     mod stack {
@@ -309,6 +492,8 @@ mod test {
                     use super::*;
                     $(
                         #[test]
+                        // methods that only read the stack don't need it mutable
+                        #[allow(unused_mut)]
                         fn $case_name() {
                             let mut test_stack = stack![$($init_vals),*];
                             let op_result = call_method!(test_stack, $method, $args);
@@ -327,6 +512,37 @@ mod test {
             };
         }
 
+        #[test]
+        fn test_parse_stack_value() {
+            assert_eq!(parse_stack_value("65"), Ok(65f64));
+            assert_eq!(parse_stack_value("3"), Ok(3f64));
+            assert_eq!(parse_stack_value("-2.5"), Ok(-2.5f64));
+            assert_eq!(parse_stack_value("1e3"), Ok(1000f64));
+            assert_eq!(parse_stack_value("'A'"), Ok(65f64));
+            assert_eq!(parse_stack_value(" 'B' "), Ok(66f64));
+            assert_eq!(
+                parse_stack_value("''"),
+                Err(ParseError::InvalidChar("''".to_string()))
+            );
+            assert_eq!(
+                parse_stack_value("'BC'"),
+                Err(ParseError::InvalidChar("'BC'".to_string()))
+            );
+            assert_eq!(
+                parse_stack_value("xyz"),
+                Err(ParseError::InvalidNumber("xyz".to_string()))
+            );
+            assert_eq!(parse_stack_value("  "), Err(ParseError::Empty));
+        }
+
+        #[test]
+        fn test_iter() {
+            let s = stack![1f64, 2f64, 3f64];
+            let sum: f64 = s.iter().sum();
+            assert_eq!(sum, 6f64);
+            assert_stack_eq!(s, vec![1f64, 2f64, 3f64]);
+        }
+
         #[test]
         fn test_into_iterator() {
             let s = stack![1f64, 2f64, 3f64];
@@ -334,6 +550,35 @@ mod test {
             assert_eq!(stack_vec, vec![1f64, 2f64, 3f64]);
         }
 
+        mod swap_register {
+            use super::*;
+
+            #[test]
+            fn empty_stack_empty_register() {
+                let mut test_stack = stack![];
+                assert_eq!(test_stack.swap_register(), Err(StackError::Underflow));
+                assert_eq!(test_stack.register, None);
+                assert_stack_eq!(test_stack, vec![]);
+            }
+
+            #[test]
+            fn full_stack_empty_register() {
+                let mut test_stack = stack![1f64, 2f64];
+                assert_eq!(test_stack.swap_register(), Ok(()));
+                assert_eq!(test_stack.register, Some(2f64));
+                assert_stack_eq!(test_stack, vec![1f64]);
+            }
+
+            #[test]
+            fn empty_stack_full_register() {
+                let mut test_stack = stack![];
+                test_stack.register = Some(3f64);
+                assert_eq!(test_stack.swap_register(), Ok(()));
+                assert_eq!(test_stack.register, None);
+                assert_stack_eq!(test_stack, vec![3f64]);
+            }
+        }
+
         test_stack_method! {
             method: pop,
             cases: {
@@ -346,11 +591,41 @@ mod test {
             }
         }
 
+        test_stack_method! {
+            method: bottom,
+            cases: {
+                empty_stack: [] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Ok(1f64),
+                    stack: [1f64]
+                },
+                multiple_values: [3f64, 2f64] => {
+                    result: Ok(3f64),
+                    stack: [3f64, 2f64]
+                },
+            }
+        }
+
+        test_stack_method! {
+            method: pop_front,
+            cases: {
+                empty_stack: [] => (Err(StackError::Underflow)),
+                single_value: [1f64] => (Ok(1f64)),
+                multiple_values: [3f64, 2f64] => {
+                    result: Ok(3f64),
+                    stack: [2f64]
+                },
+            }
+        }
+
         test_stack_method! {
             method: add,
             cases: {
                 empty_stack: [] => (Err(StackError::Underflow)), // why does this need parentheses?
-                single_value: [1f64] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
                 multiple_values: [1f64, 2f64] => {
                     result: Ok(()),
                     stack: [3f64]
@@ -362,7 +637,10 @@ mod test {
             method: subtract,
             cases: {
                 empty_stack: [] => (Err(StackError::Underflow)), // why does this need parentheses?
-                single_value: [1f64] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
                 multiple_values: [3f64, 1f64] => {
                     result: Ok(()),
                     stack: [2f64]
@@ -374,7 +652,10 @@ mod test {
             method: multiply,
             cases: {
                 empty_stack: [] => (Err(StackError::Underflow)), // why does this need parentheses?
-                single_value: [1f64] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
                 multiple_values: [3f64, 2f64] => {
                     result: Ok(()),
                     stack: [6f64]
@@ -386,7 +667,10 @@ mod test {
             method: divide,
             cases: {
                 empty_stack: [] => (Err(StackError::Underflow)), // why does this need parentheses?
-                single_value: [1f64] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
                 multiple_values: [10f64, 5f64] => {
                     result: Ok(()),
                     stack: [2f64]
@@ -395,6 +679,10 @@ mod test {
                     result: Ok(()),
                     stack: [0.5f64]
                 },
+                zero_divisor: [5f64, 0f64] => {
+                    result: Err(StackError::DivisionByZero),
+                    stack: [5f64, 0f64]
+                },
             }
         }
 
@@ -402,11 +690,18 @@ mod test {
             method: modulo,
             cases: {
                 empty_stack: [] => (Err(StackError::Underflow)), // why does this need parentheses?
-                single_value: [1f64] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
                 multiple_values: [10f64, 3f64] => {
                     result: Ok(()),
                     stack: [1f64]
                 },
+                zero_divisor: [5f64, 0f64] => {
+                    result: Err(StackError::DivisionByZero),
+                    stack: [5f64, 0f64]
+                },
             }
         }
 
@@ -414,7 +709,10 @@ mod test {
             method: equals,
             cases: {
                 empty_stack: [] => (Err(StackError::Underflow)), // why does this need parentheses?
-                single_value: [1f64] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
                 inequal_values: [10f64, 3f64] => {
                     result: Ok(()),
                     stack: [0f64]
@@ -423,6 +721,39 @@ mod test {
                     result: Ok(()),
                     stack: [1f64]
                 },
+                large_equal_values: [(0.1f64 + 0.2f64) * 1e10f64, 0.3f64 * 1e10f64] => {
+                    result: Ok(()),
+                    stack: [1f64]
+                },
+                large_inequal_values: [1e10f64, 1e10f64 + 1f64] => {
+                    result: Ok(()),
+                    stack: [0f64]
+                },
+            }
+        }
+
+        test_stack_method! {
+            name: equals_within,
+            method: equals_within,
+            args: (1e-9f64),
+            cases: {
+                empty_stack: [] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
+                large_values: [1e10f64, 1e10f64 + 1f64] => {
+                    result: Ok(()),
+                    stack: [1f64]
+                },
+                small_values: [0f64, 1e-10f64] => {
+                    result: Ok(()),
+                    stack: [1f64]
+                },
+                inequal_values: [1f64, 1.001f64] => {
+                    result: Ok(()),
+                    stack: [0f64]
+                },
             }
         }
 
@@ -430,7 +761,10 @@ mod test {
             method: greater_than,
             cases: {
                 empty_stack: [] => (Err(StackError::Underflow)),
-                single_value: [1f64] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
                 lesser_value: [1f64, 3f64] => {
                     result: Ok(()),
                     stack: [0f64]
@@ -450,7 +784,10 @@ mod test {
             method: less_than,
             cases: {
                 empty_stack: [] => (Err(StackError::Underflow)),
-                single_value: [1f64] => (Err(StackError::Underflow)),
+                single_value: [1f64] => {
+                    result: Err(StackError::Underflow),
+                    stack: [1f64]
+                },
                 greater_value: [10f64, 3f64] => {
                     result: Ok(()),
                     stack: [0f64]
@@ -594,4 +931,154 @@ mod test {
             }
         }
     }
+
+    mod program_stack {
+        use super::super::*;
+
+        #[test]
+        fn test_iter_current() {
+            let mut program_stack = ProgramStack::new();
+            program_stack.top().extend(vec![1f64, 2f64, 3f64, 1f64]);
+            program_stack.split_stack().unwrap();
+
+            let entries: Vec<_> = program_stack.iter_current().cloned().collect();
+            assert_eq!(entries, vec![3f64]);
+        }
+
+        #[test]
+        fn test_register() {
+            let mut program_stack = ProgramStack::new();
+            program_stack
+                .top()
+                .extend(vec![1f64, 2f64, 3f64, 1f64, 1f64]);
+            program_stack.top().swap_register().unwrap();
+            assert_eq!(program_stack.register(), Some(1f64));
+
+            program_stack.split_stack().unwrap();
+            assert_eq!(program_stack.register(), None);
+            program_stack.drop_stack();
+            assert_eq!(program_stack.register(), Some(1f64));
+        }
+
+        #[test]
+        fn test_clone() {
+            let mut program_stack = ProgramStack::new();
+            program_stack
+                .top()
+                .extend(vec![1f64, 2f64, 3f64, 4f64, 2f64]);
+            program_stack.split_stack().unwrap();
+            program_stack.top().swap_register().unwrap();
+
+            let mut clone = program_stack.clone();
+            assert_eq!(clone, program_stack);
+            clone.top().push(5f64);
+            clone.top().swap_register().unwrap();
+            clone.drop_stack();
+            clone.top().push(6f64);
+
+            assert_eq!(program_stack.depth(), 2);
+            assert_eq!(program_stack.register(), Some(4f64));
+            assert_eq!(program_stack.flatten(), vec![1f64, 2f64, 3f64]);
+            assert_eq!(clone.depth(), 1);
+            assert_eq!(clone.flatten(), vec![1f64, 2f64, 3f64, 5f64, 4f64, 6f64]);
+        }
+
+        #[test]
+        fn test_clear_all() {
+            let mut program_stack = ProgramStack::new();
+            program_stack.top().extend(vec![1f64, 2f64, 3f64, 1f64]);
+            program_stack.top().swap_register().unwrap();
+            program_stack.top().push(1f64);
+            program_stack.split_stack().unwrap();
+            program_stack.top().push(1f64);
+            program_stack.split_stack().unwrap();
+            assert_eq!(program_stack.depth(), 3);
+
+            program_stack.clear_all();
+            assert_eq!(program_stack.depth(), 1);
+            assert_eq!(program_stack.total_len(), 0);
+            assert_eq!(program_stack.register(), None);
+        }
+
+        #[test]
+        fn test_flatten() {
+            let mut program_stack = ProgramStack::new();
+            assert_eq!(program_stack.depth(), 1);
+            assert_eq!(program_stack.flatten(), vec![]);
+
+            program_stack
+                .top()
+                .extend(vec![1f64, 2f64, 3f64, 4f64, 2f64]);
+            program_stack.split_stack().unwrap();
+            program_stack.top().extend(vec![5f64, 1f64]);
+            program_stack.split_stack().unwrap();
+            program_stack.top().push(6f64);
+
+            assert_eq!(program_stack.depth(), 3);
+            assert_eq!(program_stack.total_len(), 6);
+            assert_eq!(
+                program_stack.flatten(),
+                vec![1f64, 2f64, 3f64, 4f64, 5f64, 6f64]
+            );
+        }
+
+        #[test]
+        fn test_bottom() {
+            let mut program_stack = ProgramStack::new();
+            assert_eq!(program_stack.bottom(), Err(StackError::Underflow));
+
+            program_stack.top().extend(vec![1f64, 2f64, 3f64, 2f64]);
+            program_stack.split_stack().unwrap();
+            assert_eq!(program_stack.bottom(), Ok(2f64));
+        }
+
+        #[test]
+        fn test_pop_front() {
+            let mut program_stack = ProgramStack::new();
+            assert_eq!(program_stack.pop_front(), Err(StackError::Underflow));
+
+            program_stack.top().extend(vec![1f64, 2f64, 3f64, 2f64]);
+            program_stack.split_stack().unwrap();
+            assert_eq!(program_stack.pop_front(), Ok(2f64));
+            assert_eq!(program_stack.flatten(), vec![1f64, 3f64]);
+            assert_eq!(program_stack.depth(), 2);
+        }
+
+        #[test]
+        fn test_split_whole_stack() {
+            let mut program_stack = ProgramStack::new();
+            program_stack.top().extend(vec![1f64, 2f64, 3f64, 3f64]);
+            assert_eq!(program_stack.split_stack(), Ok(()));
+
+            assert!(program_stack.base.entries.is_empty());
+            let entries: Vec<_> = program_stack.iter_current().cloned().collect();
+            assert_eq!(entries, vec![1f64, 2f64, 3f64]);
+
+            program_stack.substacks.pop();
+            assert_eq!(program_stack.top().pop(), Err(StackError::Underflow));
+        }
+
+        #[test]
+        fn test_split_empty_substack() {
+            let mut program_stack = ProgramStack::new();
+            program_stack.top().extend(vec![1f64, 2f64, 0f64]);
+            assert_eq!(program_stack.split_stack(), Ok(()));
+
+            assert_eq!(program_stack.top().pop(), Err(StackError::Underflow));
+            let entries: Vec<_> = program_stack.base.iter().cloned().collect();
+            assert_eq!(entries, vec![1f64, 2f64]);
+        }
+
+        #[test]
+        fn test_split_too_many() {
+            let mut program_stack = ProgramStack::new();
+            program_stack.top().extend(vec![1f64, 2f64, 3f64]);
+            assert_eq!(program_stack.split_stack(), Err(StackError::Underflow));
+            assert!(program_stack.substacks.is_empty());
+            assert_eq!(
+                program_stack.top().entries,
+                VecDeque::from(vec![1f64, 2f64, 3f64])
+            );
+        }
+    }
 }
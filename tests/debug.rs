@@ -0,0 +1,52 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run_debug(name: &str, code: &str, commands: &str) -> Output {
+    let path = std::env::temp_dir().join(format!(
+        "red-cod-debug-{}-{}.fish",
+        name,
+        std::process::id()
+    ));
+    fs::write(&path, code).expect("failed to write program");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_debug"))
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run debug");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(path).ok();
+    output
+}
+
+#[test]
+fn test_debug_session() {
+    let output = run_debug("session", "12+n;", "b 2,0\nc\np\ns\np\nc\nq\n");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("breakpoint set at (2, 0)"));
+    assert!(stdout.contains("stopped at breakpoint"));
+    assert!(stdout.contains("[1, 2]"));
+    assert!(stdout.contains("[3]"));
+    assert!(stdout.contains("program finished"));
+}
+
+#[test]
+fn test_step_after_finish() {
+    let output = run_debug("finished", "1;2", "c\ns\ns\np\nq\n");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("program has already finished"));
+    assert!(stdout.contains("[1]"));
+    assert!(!stdout.contains("[1, 2]"));
+}
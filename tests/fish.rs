@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn write_program(name: &str, code: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("red-cod-{}-{}.fish", name, std::process::id()));
+    fs::write(&path, code).expect("failed to write program");
+    path
+}
+
+fn run_fish(name: &str, code: &str, args: &[&str]) -> Output {
+    let path = write_program(name, code);
+    let output = Command::new(env!("CARGO_BIN_EXE_fish"))
+        .args(args)
+        .arg(&path)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run fish");
+    fs::remove_file(path).ok();
+    output
+}
+
+fn stdout_of(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_input_eof_default() {
+    let output = run_fish("input-eof-default", "in;", &[]);
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "-1\n");
+}
+
+#[test]
+fn test_input_eof_flag() {
+    let output = run_fish("input-eof-zero", "in;", &["--input-eof", "0"]);
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "0\n");
+
+    let output = run_fish("input-eof-ten", "in;", &["--input-eof", "10"]);
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "10\n");
+}
+
+#[test]
+fn test_exit_code_underflow() {
+    let output = run_fish("exit-code-underflow", "1+;", &[]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_exit_code_invalid_instruction() {
+    let output = run_fish("exit-code-invalid", "Z;", &[]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_stack_round_trip() {
+    let stack_path = std::env::temp_dir().join(format!("red-cod-stack-{}.txt", std::process::id()));
+    let stack_arg = stack_path.to_str().unwrap();
+
+    let output = run_fish("dump-stack", "12a;", &["--dump-stack", stack_arg]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&stack_path).unwrap(), "1\n2\n10\n");
+
+    let output = run_fish("load-stack", "+*n;", &["--load-stack", stack_arg]);
+    fs::remove_file(&stack_path).ok();
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "12\n");
+}
+
+#[test]
+fn test_stats() {
+    let fizzbuzz = "0voa                            ~/?=0:\\
+ voa            oooo'Buzz'~<     /
+ >1+:aa*1+=?;::5%:{3%:@*?\\?/'zziF'oooo/
+ ^oa                 n:~~/";
+    let output = run_fish("stats", fizzbuzz, &["--stats"]);
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let steps = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("steps: "))
+        .expect("no step count in stats");
+    assert!(steps.parse::<usize>().unwrap() > 0);
+    assert!(stderr.contains("self-modified: no"));
+}
+
+#[test]
+fn test_input_flag() {
+    let output = run_fish("input-flag", "iioo;", &["--input", "ab"]);
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "ba\n");
+}
+
+#[test]
+fn test_input_file() {
+    let input_path = std::env::temp_dir().join(format!("red-cod-input-{}.txt", std::process::id()));
+    fs::write(&input_path, "xy").unwrap();
+
+    let output = run_fish(
+        "input-file",
+        "iioo;",
+        &["--input-file", input_path.to_str().unwrap()],
+    );
+    fs::remove_file(&input_path).ok();
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "yx\n");
+}
+
+#[test]
+fn test_missing_program() {
+    let output = Command::new(env!("CARGO_BIN_EXE_fish"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run fish");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: fish"));
+}
+
+#[test]
+fn test_stack_flag() {
+    let output = run_fish("stack-flag", "+n;", &["--stack", "3,4"]);
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "7\n");
+
+    let output = run_fish("stack-flag-invalid", "+n;", &["--stack", "3,x"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_print_stack() {
+    let output = run_fish(
+        "print-stack",
+        "34+",
+        &["--print-stack", "--step-limit", "3"],
+    );
+    assert_eq!(output.status.code(), Some(14));
+    assert_eq!(stdout_of(&output), "7\n");
+
+    let output = run_fish("print-stack-whole", "12 3;", &["--print-stack"]);
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "1 2 3\n");
+
+    let output = run_fish("print-stack-output", "12n;", &["--print-stack"]);
+    assert!(output.status.success());
+    assert_eq!(stdout_of(&output), "2\n");
+}
+
+#[test]
+fn test_bad_arguments() {
+    let output = run_fish("unknown-flag", "1n;", &["--bogus"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: fish"));
+    assert_eq!(stdout_of(&output), "");
+
+    let output = run_fish("extra-program", "1n;", &["other.fish"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: fish"));
+    assert_eq!(stdout_of(&output), "");
+}